@@ -0,0 +1,147 @@
+//! Benchmarks for rendering templates. The small case mirrors the vim plugin
+//! template used for the timings in the README; the large one is a template
+//! with a couple thousand files, where rendering runs on worker threads.
+#[macro_use]
+extern crate criterion;
+extern crate project_init;
+extern crate rustache;
+extern crate tempdir;
+
+use criterion::{BatchSize, Criterion};
+use project_init::includes;
+use project_init::render::*;
+use rustache::{HashBuilder, VecBuilder};
+use std::fs;
+use std::fs::File;
+use std::io::prelude::*;
+use tempdir::TempDir;
+
+const LARGE_TEMPLATE_FILES: usize = 2000;
+
+fn hash<'a>() -> HashBuilder<'a> {
+    HashBuilder::new()
+        .insert("project", "bench")
+        .insert("Project", "Bench")
+        .insert("year", 2019)
+        .insert("name", "Vanessa McHale")
+        .insert("version", "0.1.0")
+        .insert("email", "vamchale@gmail.com")
+        .insert("github_username", "vmchale")
+        .insert("license", "BSD3")
+        .insert("date", "01-01-2019")
+}
+
+fn write(dir: &TempDir, file: &str, contents: &str) {
+    File::create(dir.path().join(file))
+        .unwrap()
+        .write_all(contents.as_bytes())
+        .unwrap();
+}
+
+fn vim_template() -> TempDir {
+    let dir = TempDir::new("pi-bench-vim").unwrap();
+    write(&dir, "vimball.txt", includes::VIMBALL);
+    write(&dir, ".travis.yml", includes::VIM_TRAVIS);
+    dir
+}
+
+fn large_template() -> (TempDir, Vec<String>) {
+    let dir = TempDir::new("pi-bench-large").unwrap();
+    fs::create_dir(dir.path().join("src")).unwrap();
+    let mut files = Vec::new();
+    for i in 0..LARGE_TEMPLATE_FILES {
+        let file = format!("src/module{}_{{{{ project }}}}.txt", i);
+        write(
+            &dir,
+            &file,
+            "{{ project }} is copyright {{ year }} {{ name }} <{{ email }}>\n",
+        );
+        files.push(file);
+    }
+    (dir, files)
+}
+
+fn render_vim(c: &mut Criterion) {
+    let template = vim_template();
+    let project = template.path().to_string_lossy().to_string();
+    let hash = hash();
+    c.bench_function("render vim plugin", move |b| {
+        b.iter_batched(
+            || TempDir::new("pi-bench-out").unwrap(),
+            |out| {
+                let name = out.path().to_string_lossy().to_string();
+                let mut writer = Writer::new(Force::Overwrite, false);
+                render_dirs(
+                    vec![
+                        "doc".to_string(),
+                        "syntax".to_string(),
+                        "plugin".to_string(),
+                    ],
+                    &hash,
+                    &name,
+                    &mut writer,
+                )
+                .unwrap();
+                let files = render_files(
+                    vec![
+                        "syntax/{{ project }}.vim".to_string(),
+                        "plugin/{{ project }}.vim".to_string(),
+                        "doc/{{ project }}.txt".to_string(),
+                    ],
+                    &hash,
+                    &name,
+                    &mut writer,
+                )
+                .unwrap();
+                render_file(includes::BSD3, &name, "LICENSE", &hash, &mut writer).unwrap();
+                render_file(includes::README, &name, "README.md", &hash, &mut writer).unwrap();
+                let hash = HashBuilder::new().insert("files", files);
+                render_templates(
+                    &project,
+                    &name,
+                    &hash,
+                    Some(vec!["vimball.txt".to_string(), ".travis.yml".to_string()]),
+                    false,
+                    &mut writer,
+                )
+                .unwrap();
+            },
+            BatchSize::PerIteration,
+        )
+    });
+}
+
+fn render_large(c: &mut Criterion) {
+    let (template, files) = large_template();
+    let project = template.path().to_string_lossy().to_string();
+    let hash = hash().insert("files", VecBuilder::new());
+    let mut group = c.benchmark_group("large template");
+    group.sample_size(10);
+    group.bench_function("render 2000 files", move |b| {
+        b.iter_batched(
+            || {
+                let out = TempDir::new("pi-bench-out").unwrap();
+                fs::create_dir(out.path().join("src")).unwrap();
+                out
+            },
+            |out| {
+                let name = out.path().to_string_lossy().to_string();
+                let mut writer = Writer::new(Force::Overwrite, false);
+                render_templates(
+                    &project,
+                    &name,
+                    &hash,
+                    Some(files.clone()),
+                    false,
+                    &mut writer,
+                )
+                .unwrap();
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+criterion_group!(benches, render_vim, render_large);
+criterion_main!(benches);
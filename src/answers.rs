@@ -0,0 +1,161 @@
+//! Module for the answers file recorded in each generated project, which
+//! makes it possible to generate the same project again.
+
+use error::PiError;
+use generator::{Generator, Source};
+use std::collections::BTreeMap;
+use std::fs;
+use toml;
+
+/// Name of the answers file written into generated projects.
+pub const FILE: &str = ".pi-answers.toml";
+
+/// Kind of template a project was generated from.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    Builtin,
+    Dir,
+    Git,
+    Archive,
+    Hg,
+    Darcs,
+    Pijul,
+}
+
+/// Where the template came from, and which version of it was used.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Template {
+    pub kind: Kind,
+    /// Name of the builtin, path of the directory, URL of the repository, or
+    /// path or URL of the archive.
+    pub location: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Directory of the repository the template is in, if not at its root.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
+}
+
+/// Everything needed to generate a project again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Answers {
+    /// The license that was asked for, e.g. `MIT`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    pub template: Template,
+    /// Variables the templates were rendered with. Secrets are left out.
+    #[serde(default)]
+    pub variables: BTreeMap<String, String>,
+}
+
+/// Whether the variable `key` probably holds something that shouldn't be
+/// printed or written down, such as a password or token.
+pub fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase();
+    [
+        "password",
+        "passwd",
+        "secret",
+        "token",
+        "api_key",
+        "apikey",
+        "private_key",
+        "credential",
+    ]
+    .iter()
+    .any(|s| key.contains(s))
+}
+
+impl Answers {
+    /// Record the answers for a project generated from `source`.
+    pub fn new(
+        source: &Source,
+        revision: Option<String>,
+        license: Option<String>,
+        variables: &BTreeMap<String, String>,
+    ) -> Answers {
+        let (kind, location) = match *source {
+            Source::Builtin(ref b) => (Kind::Builtin, b.to_string()),
+            Source::Dir(ref d) => (Kind::Dir, d.to_string()),
+            Source::Git(ref url) => (Kind::Git, url.to_string()),
+            Source::Archive(ref a) => (Kind::Archive, a.to_string()),
+            Source::Hg(ref url) => (Kind::Hg, url.to_string()),
+            Source::Darcs(ref url) => (Kind::Darcs, url.to_string()),
+            Source::Pijul(ref url) => (Kind::Pijul, url.to_string()),
+        };
+        let variables = variables
+            .iter()
+            .filter(|&(k, _)| !is_secret(k))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        Answers {
+            license,
+            template: Template {
+                kind,
+                location,
+                revision,
+                subdir: None,
+            },
+            variables,
+        }
+    }
+
+    /// Read the answers file at `path`.
+    pub fn read(path: &str) -> Result<Answers, PiError> {
+        let contents =
+            fs::read_to_string(path).map_err(|_| PiError::MissingTemplate(path.to_string()))?;
+        toml::from_str(contents.trim_start_matches('\u{feff}'))
+            .map_err(|e| PiError::Parse(path.to_string(), e))
+    }
+
+    /// The answers as the contents of an answers file.
+    pub fn to_toml(&self) -> String {
+        let mut s = "# Generated by pi. Run `pi replay ".to_string();
+        s.push_str(FILE);
+        s.push_str("` to generate this project again.\n");
+        // everything here is a string, so this can't fail
+        s.push_str(&toml::to_string(self).unwrap_or_default());
+        s
+    }
+
+    /// Where the template came from.
+    pub fn source(&self) -> Source {
+        let location = self.template.location.clone();
+        match self.template.kind {
+            Kind::Builtin => Source::Builtin(location),
+            Kind::Dir => Source::Dir(location),
+            Kind::Git => Source::Git(location),
+            Kind::Archive => Source::Archive(location),
+            Kind::Hg => Source::Hg(location),
+            Kind::Darcs => Source::Darcs(location),
+            Kind::Pijul => Source::Pijul(location),
+        }
+    }
+
+    /// A generator that renders the same template, at the same revision,
+    /// with the same answers into the directory `name`.
+    pub fn generator(&self, name: &str) -> Generator {
+        let revision = self.template.revision.as_deref();
+        self.generator_for(self.source(), revision, name)
+    }
+
+    /// A generator that renders `source` at `revision` with these answers
+    /// into the directory `name`.
+    pub fn generator_for(&self, source: Source, revision: Option<&str>, name: &str) -> Generator {
+        let mut generator = Generator::new(source, name);
+        if let Some(revision) = revision {
+            generator = generator.revision(revision);
+        }
+        if let Some(ref subdir) = self.template.subdir {
+            generator = generator.subdir(subdir);
+        }
+        if let Some(ref license) = self.license {
+            generator = generator.license(license);
+        }
+        for (key, value) in &self.variables {
+            generator = generator.variable(key, value);
+        }
+        generator
+    }
+}
@@ -0,0 +1,369 @@
+//! Module for tar and zip archives: writing generated projects into them, and
+//! unpacking templates from them.
+extern crate flate2;
+extern crate tar;
+extern crate zip;
+
+use self::flate2::read::GzDecoder;
+use self::flate2::write::GzEncoder;
+use self::flate2::Compression;
+use self::zip::write::FileOptions;
+use self::zip::{DateTime, ZipArchive, ZipWriter};
+use error::PiError;
+use remote;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::io::prelude::*;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use time;
+
+/// Kind of archive to write.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Tar,
+    TarGz,
+    Zip,
+}
+
+impl Format {
+    /// Guess the format from a file name such as `project.tar.gz`.
+    pub fn from_path(path: &str) -> Option<Format> {
+        let lower = path.to_lowercase();
+        if lower.ends_with(".tar.gz") || lower.ends_with(".tgz") {
+            Some(Format::TarGz)
+        } else if lower.ends_with(".tar") {
+            Some(Format::Tar)
+        } else if lower.ends_with(".zip") {
+            Some(Format::Zip)
+        } else {
+            None
+        }
+    }
+
+    /// Recognize an archive from its first few bytes.
+    pub fn sniff(contents: &[u8]) -> Option<Format> {
+        if contents.starts_with(&[0x1f, 0x8b]) {
+            Some(Format::TarGz)
+        } else if contents.starts_with(b"PK\x03\x04") || contents.starts_with(b"PK\x05\x06") {
+            Some(Format::Zip)
+        } else if contents.get(257..262) == Some(b"ustar") {
+            Some(Format::Tar)
+        } else {
+            None
+        }
+    }
+}
+
+/// A file or directory held in memory until the archive is written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Entry {
+    Dir,
+    File { contents: Vec<u8>, mode: u32 },
+}
+
+/// Write `entries` to `sink` as an archive, returning the sink once the
+/// archive is complete. Entries are written in path order, so directories
+/// come before what's in them.
+pub fn write<W: Write + Seek>(
+    format: Format,
+    entries: &BTreeMap<String, Entry>,
+    sink: W,
+) -> io::Result<W> {
+    match format {
+        Format::Tar => write_tar(entries, sink),
+        Format::TarGz => {
+            let encoder = write_tar(entries, GzEncoder::new(sink, Compression::default()))?;
+            encoder.finish()
+        }
+        Format::Zip => write_zip(entries, sink),
+    }
+}
+
+fn write_tar<W: Write>(entries: &BTreeMap<String, Entry>, sink: W) -> io::Result<W> {
+    let mtime = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let mut builder = tar::Builder::new(sink);
+    for (path, entry) in entries {
+        let mut header = tar::Header::new_gnu();
+        header.set_mtime(mtime);
+        match *entry {
+            Entry::Dir => {
+                header.set_entry_type(tar::EntryType::Directory);
+                header.set_mode(0o755);
+                header.set_size(0);
+                builder.append_data(&mut header, path, io::empty())?;
+            }
+            Entry::File { ref contents, mode } => {
+                header.set_entry_type(tar::EntryType::Regular);
+                header.set_mode(mode);
+                header.set_size(contents.len() as u64);
+                builder.append_data(&mut header, path, contents.as_slice())?;
+            }
+        }
+    }
+    builder.into_inner()
+}
+
+fn write_zip<W: Write + Seek>(entries: &BTreeMap<String, Entry>, sink: W) -> io::Result<W> {
+    let now = time::now();
+    let options = match DateTime::from_date_and_time(
+        (now.tm_year + 1900) as u16,
+        (now.tm_mon + 1) as u8,
+        now.tm_mday as u8,
+        now.tm_hour as u8,
+        now.tm_min as u8,
+        now.tm_sec.min(59) as u8,
+    ) {
+        Ok(t) => FileOptions::default().last_modified_time(t),
+        Err(_) => FileOptions::default(),
+    };
+    let mut zip = ZipWriter::new(sink);
+    for (path, entry) in entries {
+        match *entry {
+            Entry::Dir => {
+                zip.add_directory(path.as_str(), options.unix_permissions(0o755))?;
+            }
+            Entry::File { ref contents, mode } => {
+                let options = options.unix_permissions(mode);
+                zip.start_file(path.as_str(), options)?;
+                zip.write_all(contents)?;
+            }
+        }
+    }
+    Ok(zip.finish()?)
+}
+
+/// Unpack the archive `contents` into `dest`. Entries that would end up
+/// outside of `dest` are refused.
+pub fn unpack(format: Format, contents: &[u8], dest: &Path) -> io::Result<()> {
+    match format {
+        Format::Tar => tar::Archive::new(contents).unpack(dest),
+        Format::TarGz => tar::Archive::new(GzDecoder::new(contents)).unpack(dest),
+        Format::Zip => Ok(ZipArchive::new(io::Cursor::new(contents))?.extract(dest)?),
+    }
+}
+
+/// Download or read the archive at `location`, a path or an HTTP URL, and
+/// unpack it into `dest`. Returns the archive's contents.
+pub fn fetch(location: &str, dest: &Path) -> Result<Vec<u8>, PiError> {
+    let contents = if remote::is_http(location) {
+        remote::download(location)?
+    } else {
+        fs::read(location).map_err(|_| PiError::MissingTemplate(location.to_string()))?
+    };
+    // URLs may end in a query string rather than the file name
+    let format = Format::sniff(&contents)
+        .or_else(|| Format::from_path(location))
+        .ok_or_else(|| PiError::UnknownArchive(location.to_string()))?;
+    unpack(format, &contents, dest).map_err(|e| PiError::Unpack(location.to_string(), e))?;
+    Ok(contents)
+}
+
+/// Where the template unpacked into `dest` is. Archives often wrap everything
+/// in a directory named after them, so if that's all there is, it's that
+/// directory.
+pub fn root(dest: &Path) -> PathBuf {
+    if dest.join("template.toml").exists() {
+        return dest.to_path_buf();
+    }
+    let entries: Vec<PathBuf> = match fs::read_dir(dest) {
+        Ok(r) => r.flatten().map(|e| e.path()).collect(),
+        Err(_) => Vec::new(),
+    };
+    match entries.as_slice() {
+        [only] if only.is_dir() => only.clone(),
+        _ => dest.to_path_buf(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+    use tempdir::TempDir;
+
+    fn template() -> BTreeMap<String, Entry> {
+        let file = |contents: &str, mode| Entry::File {
+            contents: contents.as_bytes().to_vec(),
+            mode,
+        };
+        let mut entries = BTreeMap::new();
+        entries.insert("t".to_string(), Entry::Dir);
+        entries.insert(
+            "t/template.toml".to_string(),
+            file("license = \"MIT\"\n", 0o644),
+        );
+        entries.insert("t/src".to_string(), Entry::Dir);
+        entries.insert("t/src/run.sh".to_string(), file("#!/bin/sh\n", 0o755));
+        entries
+    }
+
+    fn archive(format: Format, entries: &BTreeMap<String, Entry>) -> Vec<u8> {
+        write(format, entries, io::Cursor::new(Vec::new()))
+            .unwrap()
+            .into_inner()
+    }
+
+    // Serve `body` with `status` to a single request, returning the URL.
+    fn serve(status: &'static str, body: Vec<u8>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => request.extend_from_slice(&buf[..n]),
+                }
+            }
+            let head = format!(
+                "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                status,
+                body.len()
+            );
+            let _ = stream.write_all(head.as_bytes());
+            let _ = stream.write_all(&body);
+        });
+        format!("http://127.0.0.1:{}", port)
+    }
+
+    fn assert_unpacked(dest: &Path) {
+        let root = root(dest);
+        assert_eq!(root, dest.join("t"));
+        assert_eq!(
+            fs::read_to_string(root.join("template.toml")).unwrap(),
+            "license = \"MIT\"\n"
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("src").join("run.sh")).unwrap(),
+            "#!/bin/sh\n"
+        );
+        #[cfg(not(target_os = "windows"))]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(root.join("src").join("run.sh"))
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o111, 0o111);
+        }
+    }
+
+    #[test]
+    fn formats_from_paths() {
+        assert_eq!(Format::from_path("t.tar.gz"), Some(Format::TarGz));
+        assert_eq!(Format::from_path("T.TGZ"), Some(Format::TarGz));
+        assert_eq!(Format::from_path("t.tar"), Some(Format::Tar));
+        assert_eq!(Format::from_path("t.zip"), Some(Format::Zip));
+        assert_eq!(Format::from_path("t.git"), None);
+    }
+
+    #[test]
+    fn formats_from_contents() {
+        for &format in &[Format::Tar, Format::TarGz, Format::Zip] {
+            assert_eq!(Format::sniff(&archive(format, &template())), Some(format));
+        }
+        assert_eq!(Format::sniff(b"license = \"MIT\""), None);
+    }
+
+    #[test]
+    fn fetch_files() {
+        for &(name, format) in &[
+            ("t.tar", Format::Tar),
+            ("t.tar.gz", Format::TarGz),
+            ("t.zip", Format::Zip),
+        ] {
+            let dir = TempDir::new("pi-archive").unwrap();
+            let path = dir.path().join(name);
+            let contents = archive(format, &template());
+            fs::write(&path, &contents).unwrap();
+            let dest = dir.path().join("unpacked");
+            let fetched = fetch(&path.to_string_lossy(), &dest).unwrap();
+            assert_eq!(fetched, contents);
+            assert_unpacked(&dest);
+        }
+    }
+
+    #[test]
+    fn fetch_over_http() {
+        let contents = archive(Format::TarGz, &template());
+        // no file name to go by, only what's in it
+        let url = serve("200 OK", contents.clone()) + "/download?id=1";
+        let dir = TempDir::new("pi-archive").unwrap();
+        assert_eq!(fetch(&url, dir.path()).unwrap(), contents);
+        assert_unpacked(dir.path());
+    }
+
+    #[test]
+    fn fetch_errors() {
+        let dir = TempDir::new("pi-archive").unwrap();
+        let missing = dir.path().join("missing.tar.gz");
+        assert!(matches!(
+            fetch(&missing.to_string_lossy(), dir.path()),
+            Err(PiError::MissingTemplate(_))
+        ));
+        let text = dir.path().join("t.txt");
+        fs::write(&text, "not an archive").unwrap();
+        assert!(matches!(
+            fetch(&text.to_string_lossy(), dir.path()),
+            Err(PiError::UnknownArchive(_))
+        ));
+        let bad = dir.path().join("bad.zip");
+        fs::write(&bad, "not a zip").unwrap();
+        assert!(matches!(
+            fetch(&bad.to_string_lossy(), dir.path()),
+            Err(PiError::Unpack(_, _))
+        ));
+        let url = serve("404 Not Found", Vec::new()) + "/t.tar.gz";
+        assert!(matches!(
+            fetch(&url, dir.path()),
+            Err(PiError::Download(_, _))
+        ));
+    }
+
+    #[test]
+    fn unpack_stays_inside() {
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_mode(0o644);
+        header.set_size(1);
+        // the tar crate won't write such a path itself
+        header.as_old_mut().name[..9].copy_from_slice(b"../escape");
+        header.set_cksum();
+        let mut builder = tar::Builder::new(Vec::new());
+        builder.append(&header, &b"x"[..]).unwrap();
+        let contents = builder.into_inner().unwrap();
+        let dir = TempDir::new("pi-archive").unwrap();
+        let dest = dir.path().join("unpacked");
+        let _ = unpack(Format::Tar, &contents, &dest);
+        assert!(!dir.path().join("escape").exists());
+    }
+
+    #[test]
+    fn root_of_unpacked_templates() {
+        let dir = TempDir::new("pi-archive").unwrap();
+        let dest = dir.path();
+        // nothing there
+        assert_eq!(root(dest), dest);
+        // a single directory
+        fs::create_dir(dest.join("t-1.0")).unwrap();
+        assert_eq!(root(dest), dest.join("t-1.0"));
+        // ... unless the template is at the top
+        fs::write(dest.join("template.toml"), "").unwrap();
+        assert_eq!(root(dest), dest);
+        fs::remove_file(dest.join("template.toml")).unwrap();
+        // or there's something next to it
+        fs::write(dest.join("README.md"), "").unwrap();
+        assert_eq!(root(dest), dest);
+        // a single file isn't a root
+        fs::remove_dir(dest.join("t-1.0")).unwrap();
+        assert_eq!(root(dest), dest);
+    }
+}
@@ -0,0 +1,312 @@
+//! Module for the local cache of git templates, kept under
+//! `$XDG_CACHE_HOME/pi/templates`.
+extern crate dirs;
+
+use colored::*;
+use error::PiError;
+use git2::Repository;
+use manifest::sha256;
+use remote;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+use toml;
+use types::GitConfig;
+
+/// A repository in the cache, checked out at some revision.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Entry {
+    pub url: String,
+    /// The revision asked for, or none for the default branch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// The file or directory that was checked out, if not the whole
+    /// repository.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sparse: Option<String>,
+    /// Where the repository is checked out.
+    #[serde(skip)]
+    pub path: PathBuf,
+}
+
+impl Entry {
+    /// The commit that is checked out, if it can be read.
+    pub fn commit(&self) -> Option<String> {
+        let repo = Repository::open(&self.path).ok()?;
+        let commit = repo.head().ok()?.peel_to_commit().ok()?;
+        Some(commit.id().to_string())
+    }
+
+    // A commit can't change, so there's no need to check for updates.
+    fn is_pinned(&self) -> bool {
+        self.revision
+            .as_ref()
+            .map(|r| r.len() == 40 && r.chars().all(|c| c.is_ascii_hexdigit()))
+            .unwrap_or(false)
+    }
+
+    // Fetch from the remote and check out the revision again.
+    fn update(&self, git: &GitConfig) -> Result<(), PiError> {
+        let repo = Repository::open(&self.path).map_err(|e| PiError::Clone(self.url.clone(), e))?;
+        let revision = self.revision.as_deref();
+        let rev =
+            remote::fetch(&repo, revision, git).map_err(|e| PiError::Clone(self.url.clone(), e))?;
+        remote::checkout(&repo, &rev, self.sparse.as_deref())
+            .map_err(|e| PiError::Revision(revision.unwrap_or("HEAD").to_string(), e))
+    }
+}
+
+/// A template fetched for one generation: either a copy in the cache, or a
+/// temporary clone if there's no cache directory.
+pub struct Fetched {
+    path: PathBuf,
+    _temp: Option<TempDir>,
+}
+
+impl Fetched {
+    /// Where the repository is checked out.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// The directory templates are cached in.
+pub fn dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|d| d.join("pi").join("templates"))
+}
+
+// Directory name for the repository at `url`, checked out at `revision`, and
+// only at `sparse` if given: the repository's name, to make it recognizable,
+// and a hash.
+fn key(url: &str, revision: Option<&str>, sparse: Option<&str>) -> String {
+    let name = url
+        .trim_end_matches('/')
+        .trim_end_matches(".git")
+        .rsplit(['/', ':'])
+        .next()
+        .unwrap_or("");
+    let mut id = url.to_string();
+    id.push('\n');
+    id.push_str(revision.unwrap_or(""));
+    if let Some(sparse) = sparse {
+        id.push('\n');
+        id.push_str(sparse);
+    }
+    let mut key: String = name
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric() || *c == '-' || *c == '_')
+        .collect();
+    key.push('-');
+    key.push_str(&sha256(id.as_bytes())[..16]);
+    key
+}
+
+/// Get the repository at `url`, checked out at `revision`, from the cache.
+/// With `sparse`, only that file or directory is checked out. Unless
+/// `offline` is set, it's cloned if it isn't cached yet, and updated if
+/// `revision` is a branch or tag (or not given). `git` says how to
+/// authenticate.
+pub fn fetch(
+    url: &str,
+    revision: Option<&str>,
+    sparse: Option<&str>,
+    offline: bool,
+    git: &GitConfig,
+) -> Result<Fetched, PiError> {
+    let root = match dir() {
+        Some(d) => d,
+        None if offline => return Err(PiError::NotCached(url.to_string())),
+        None => {
+            let temp = remote::clone(url, revision, sparse, git)?;
+            return Ok(Fetched {
+                path: temp.path().to_path_buf(),
+                _temp: Some(temp),
+            });
+        }
+    };
+    let path = root.join(key(url, revision, sparse));
+    let entry = Entry {
+        url: url.to_string(),
+        revision: revision.map(|r| r.to_string()),
+        sparse: sparse.map(|s| s.to_string()),
+        path: path.clone(),
+    };
+    if path.is_dir() {
+        if !offline && !entry.is_pinned() {
+            if let Err(e) = entry.update(git) {
+                eprintln!(
+                    "{}: could not update {} ({}), using the cached copy",
+                    "Warning".yellow(),
+                    url,
+                    e
+                );
+            }
+        }
+    } else if offline {
+        return Err(PiError::NotCached(url.to_string()));
+    } else {
+        // clone next to where it goes, so an interrupted clone leaves nothing behind
+        fs::create_dir_all(&root)?;
+        let temp = TempDir::new_in(&root, ".clone")?;
+        remote::clone_into(url, temp.path(), revision, sparse, git)?;
+        fs::rename(temp.path(), &path)?;
+        let _ = temp.into_path();
+        let meta = toml::to_string(&entry).unwrap_or_default();
+        fs::write(path.with_extension("toml"), meta)?;
+    }
+    Ok(Fetched { path, _temp: None })
+}
+
+/// Everything in the cache.
+pub fn list() -> Result<Vec<Entry>, PiError> {
+    match dir() {
+        Some(root) => list_in(&root),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Everything in the cache at `root`.
+fn list_in(root: &Path) -> Result<Vec<Entry>, PiError> {
+    let mut entries = Vec::new();
+    let read = match fs::read_dir(root) {
+        Ok(r) => r,
+        Err(_) => return Ok(entries),
+    };
+    for file in read.flatten() {
+        let meta = file.path();
+        if meta.extension().map(|e| e == "toml") != Some(true) {
+            continue;
+        }
+        // keys never start with a dot, and `..toml` would stand for the cache itself
+        if file.file_name().to_string_lossy().starts_with('.') {
+            continue;
+        }
+        let contents = fs::read_to_string(&meta)?;
+        let mut entry: Entry = toml::from_str(&contents)
+            .map_err(|e| PiError::Parse(meta.to_string_lossy().to_string(), e))?;
+        entry.path = meta.with_extension("");
+        entries.push(entry);
+    }
+    entries.sort_by(|a, b| (&a.url, &a.revision, &a.sparse).cmp(&(&b.url, &b.revision, &b.sparse)));
+    Ok(entries)
+}
+
+/// Remove everything cached from `url`, or everything if `url` isn't given.
+/// Returns what was removed.
+pub fn clean(url: Option<&str>) -> Result<Vec<Entry>, PiError> {
+    match dir() {
+        Some(root) => clean_in(&root, url),
+        None => Ok(Vec::new()),
+    }
+}
+
+// Remove what's cached from `url` in the cache at `root`. Only what's
+// directly in `root` is removed; a link to elsewhere is removed, not followed.
+fn clean_in(root: &Path, url: Option<&str>) -> Result<Vec<Entry>, PiError> {
+    let mut removed = Vec::new();
+    for entry in list_in(root)? {
+        if url.map(|u| u == entry.url).unwrap_or(true) {
+            match fs::symlink_metadata(&entry.path) {
+                Ok(ref m) if m.is_dir() => fs::remove_dir_all(&entry.path)?,
+                Ok(_) => fs::remove_file(&entry.path)?,
+                Err(_) => (),
+            }
+            fs::remove_file(entry.path.with_extension("toml"))?;
+            removed.push(entry);
+        }
+    }
+    Ok(removed)
+}
+
+/// Update everything cached from `url`, or everything if `url` isn't given.
+/// Returns what was updated; commits are left alone, as they can't change.
+pub fn refresh(url: Option<&str>, git: &GitConfig) -> Result<Vec<Entry>, PiError> {
+    let mut refreshed = Vec::new();
+    for entry in list()? {
+        if url.map(|u| u == entry.url).unwrap_or(true) && !entry.is_pinned() {
+            entry.update(git)?;
+            refreshed.push(entry);
+        }
+    }
+    Ok(refreshed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_is_named_after_the_repository() {
+        let k = key("https://github.com/vmchale/rust-template.git", None, None);
+        assert!(k.starts_with("rust-template-"));
+        assert_eq!(k.len(), "rust-template-".len() + 16);
+        assert!(key("git@github.com:vmchale/t", None, None).starts_with("t-"));
+    }
+
+    #[test]
+    fn key_depends_on_url_and_revision() {
+        let url = "https://github.com/vmchale/t";
+        let k = key(url, Some("v1"), None);
+        assert_eq!(k, key(url, Some("v1"), None));
+        assert_ne!(k, key(url, Some("v2"), None));
+        assert_ne!(k, key(url, None, None));
+        assert_ne!(k, key("https://gitlab.com/vmchale/t", Some("v1"), None));
+        assert_ne!(k, key(url, Some("v1"), Some("sub")));
+    }
+
+    // An entry for `url` in the cache at `root`, under `name`.
+    fn cached(root: &Path, name: &str, url: &str) {
+        fs::create_dir(root.join(name)).unwrap();
+        fs::write(root.join(name).join("template.toml"), "").unwrap();
+        let meta = format!("url = {:?}\n", url);
+        fs::write(root.join(name).with_extension("toml"), meta).unwrap();
+    }
+
+    #[test]
+    fn clean_removes_entries_for_the_url() {
+        let dir = TempDir::new("pi-cache").unwrap();
+        let root = dir.path();
+        cached(root, "a-1", "https://example.com/a");
+        cached(root, "b-2", "https://example.com/b");
+        let removed = clean_in(root, Some("https://example.com/a")).unwrap();
+        assert_eq!(removed.len(), 1);
+        assert!(!root.join("a-1").exists());
+        assert!(!root.join("a-1.toml").exists());
+        assert!(root.join("b-2").is_dir());
+        assert_eq!(clean_in(root, None).unwrap().len(), 1);
+        assert!(!root.join("b-2").exists());
+    }
+
+    #[test]
+    fn clean_stays_inside_the_cache() {
+        let dir = TempDir::new("pi-cache").unwrap();
+        let root = dir.path().join("templates");
+        fs::create_dir(&root).unwrap();
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("keep"), "").unwrap();
+        // a metadata file standing for the cache itself
+        fs::write(root.join("..toml"), "url = \"u\"\n").unwrap();
+        cached(&root, "a-1", "u");
+        assert_eq!(clean_in(&root, None).unwrap().len(), 1);
+        assert!(root.is_dir());
+        assert!(root.join("..toml").exists());
+        assert!(outside.join("keep").exists());
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn clean_removes_links_without_following_them() {
+        let dir = TempDir::new("pi-cache").unwrap();
+        let root = dir.path().join("templates");
+        fs::create_dir(&root).unwrap();
+        let outside = dir.path().join("outside");
+        fs::create_dir(&outside).unwrap();
+        fs::write(outside.join("keep"), "").unwrap();
+        std::os::unix::fs::symlink(&outside, root.join("a-1")).unwrap();
+        fs::write(root.join("a-1.toml"), "url = \"u\"\n").unwrap();
+        assert_eq!(clean_in(&root, None).unwrap().len(), 1);
+        assert!(fs::symlink_metadata(root.join("a-1")).is_err());
+        assert!(outside.join("keep").exists());
+    }
+}
@@ -0,0 +1,62 @@
+//! Module for catalogs of the templates in a repository.
+
+use cache;
+use error::PiError;
+use std::fs;
+use std::path::Path;
+use toml;
+use types::GitConfig;
+
+/// Name of the catalog at the root of a repository.
+pub const FILE: &str = "templates.toml";
+
+/// A template listed in a catalog.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    /// Directory of the repository containing the template.
+    pub path: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Catalog {
+    #[serde(default, rename = "template")]
+    templates: Vec<Entry>,
+}
+
+/// Read the catalog at the root of `dir`, if there is one.
+pub fn read(dir: &Path) -> Result<Option<Vec<Entry>>, PiError> {
+    let file = dir.join(FILE);
+    let contents = match fs::read_to_string(&file) {
+        Ok(c) => c,
+        Err(_) => return Ok(None),
+    };
+    let catalog: Catalog = toml::from_str(&contents)
+        .map_err(|e| PiError::Parse(file.to_string_lossy().to_string(), e))?;
+    Ok(Some(catalog.templates))
+}
+
+/// Fetch the catalog of the repository at `url`, as of `revision` if given.
+/// With `offline`, only the cached copy is used.
+pub fn fetch(
+    url: &str,
+    revision: Option<&str>,
+    offline: bool,
+    git: &GitConfig,
+) -> Result<Vec<Entry>, PiError> {
+    // only the catalog itself is needed
+    let dir = cache::fetch(url, revision, Some(FILE), offline, git)?;
+    read(dir.path())?.ok_or_else(|| PiError::NoCatalog(url.to_string()))
+}
+
+/// The template called `name`.
+pub fn find<'a>(entries: &'a [Entry], name: &str) -> Result<&'a Entry, PiError> {
+    entries.iter().find(|e| e.name == name).ok_or_else(|| {
+        let names = entries.iter().map(|e| e.name.clone()).collect();
+        PiError::UnknownTemplate(name.to_string(), names)
+    })
+}
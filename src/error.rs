@@ -0,0 +1,207 @@
+//! Module containing the error type returned by the library.
+
+use git2;
+use std::error::Error;
+use std::fmt;
+use std::io;
+use toml;
+
+/// Everything that can go wrong while generating a project.
+#[derive(Debug)]
+pub enum PiError {
+    /// Reading or writing failed for some other reason.
+    Io(io::Error),
+    /// The `template.toml` at the given path could not be opened.
+    MissingTemplate(String),
+    /// There is no builtin template by that name.
+    UnknownBuiltin(String),
+    /// The repository at the given URL could not be cloned.
+    Clone(String, git2::Error),
+    /// The repository at the given URL could not be cloned by its version
+    /// control tool.
+    VcsClone(String, io::Error),
+    /// A template of the given name is already installed, at the given path.
+    AlreadyInstalled(String, String),
+    /// No template of the given name was installed with `pi template install`.
+    NotInstalled(String),
+    /// The name to install a template under isn't a plain directory name.
+    InvalidTemplateName(String),
+    /// The requested revision of a git template could not be checked out.
+    Revision(String, git2::Error),
+    /// The file at the given URL could not be downloaded, for the given
+    /// reason.
+    Download(String, String),
+    /// The archive at the given location could not be unpacked.
+    Unpack(String, io::Error),
+    /// Working offline, and the repository at the given URL isn't cached.
+    NotCached(String),
+    /// There is no template by that name in the repository's catalog, which
+    /// has the given templates.
+    UnknownTemplate(String, Vec<String>),
+    /// The repository at the given URL has no catalog of templates.
+    NoCatalog(String),
+    /// No project name was given for the repository at the given URL, which
+    /// has no catalog of templates to list instead.
+    NameRequired(String),
+    /// The repository at the given URL has a catalog of templates, but none
+    /// of them was picked.
+    ChooseTemplate(String, Vec<String>),
+    /// A file listed in `template.toml` could not be read.
+    MissingTemplateFile(String),
+    /// A configuration file could not be parsed.
+    Parse(String, toml::de::Error),
+    /// A mustache template could not be rendered.
+    Render(String, String),
+    /// The license requested isn't one we know about.
+    InvalidLicense(String),
+    /// The answers file at the given path doesn't say where to put the
+    /// project.
+    NoProjectName(String),
+    /// The project can't be upgraded, for the given reason.
+    NotUpgradable(String),
+    /// Files that were changed since they were generated, and so weren't
+    /// removed.
+    Modified(Vec<String>),
+    /// The manifest at the given path lists a path outside of the project.
+    OutsideProject(String, String),
+    /// The project directory (or archive) already exists.
+    Exists(String),
+    /// The archive name doesn't end in an extension we know.
+    UnknownArchive(String),
+    /// A generated file could not be written.
+    CreateFile(String, io::Error),
+    /// No temporary directory could be made next to the project.
+    Stage(String, io::Error),
+    /// The finished project could not be moved into place.
+    Commit(String, io::Error),
+    /// The version control tool failed to initialize the project.
+    Vcs(String, io::Error),
+}
+
+impl fmt::Display for PiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            PiError::Io(ref e) => write!(f, "{}", e),
+            PiError::MissingTemplate(ref p) => write!(
+                f,
+                "File {:?} could not be opened. Check that it exists.",
+                p
+            ),
+            PiError::UnknownBuiltin(ref t) => {
+                write!(f, "The requested template {} is not a built-in :(", t)
+            }
+            PiError::Clone(ref url, ref e) => write!(f, "failed to clone repo at {}: {}", url, e),
+            PiError::VcsClone(ref url, ref e) => {
+                write!(f, "failed to clone repo at {}: {}", url, e)
+            }
+            PiError::AlreadyInstalled(ref name, ref p) => write!(
+                f,
+                "a template called {} is already installed in {}. Run pi template update to get its latest version, or remove it first.",
+                name, p
+            ),
+            PiError::InvalidTemplateName(ref name) => write!(
+                f,
+                "{:?} can't be used as the name of a template, as it isn't a plain directory name",
+                name
+            ),
+            PiError::NotInstalled(ref name) => write!(
+                f,
+                "no template called {} was installed with pi template install",
+                name
+            ),
+            PiError::Revision(ref r, ref e) => write!(f, "failed to check out revision {}: {}", r, e),
+            PiError::Download(ref url, ref e) => write!(f, "failed to download {}: {}", url, e),
+            PiError::Unpack(ref a, ref e) => write!(f, "failed to unpack {}: {}", a, e),
+            PiError::NotCached(ref url) => write!(
+                f,
+                "{} is not cached. Rerun without --offline to fetch it.",
+                url
+            ),
+            PiError::UnknownTemplate(ref t, ref names) => write!(
+                f,
+                "there is no template {} in the catalog. Available templates are: {}",
+                t,
+                names.join(", ")
+            ),
+            PiError::NoCatalog(ref url) => {
+                write!(f, "{} has no catalog of templates (templates.toml)", url)
+            }
+            PiError::NameRequired(ref url) => write!(
+                f,
+                "a project name is required, as {} has no catalog of templates to list",
+                url
+            ),
+            PiError::ChooseTemplate(ref url, ref names) => write!(
+                f,
+                "{} has several templates. Pick one with --template: {}",
+                url,
+                names.join(", ")
+            ),
+            PiError::MissingTemplateFile(ref p) => write!(f, "Failed to open file: {:?}", p),
+            PiError::Parse(ref p, ref e) => write!(f, "Error parsing {:?}: {}", p, e),
+            PiError::Render(ref t, ref e) => write!(f, "failed to render {:?}: {}", t, e),
+            PiError::InvalidLicense(ref l) => write!(f, "requested license {} not found", l),
+            PiError::NoProjectName(ref p) => write!(
+                f,
+                "{:?} has no project variable. Pass a directory to generate the project into.",
+                p
+            ),
+            PiError::NotUpgradable(ref r) => write!(f, "can't upgrade: {}", r),
+            PiError::Modified(ref m) => write!(
+                f,
+                "not removing anything, as these files were changed since they were generated: {}. Rerun with --keep-modified to remove everything else.",
+                m.join(", ")
+            ),
+            PiError::OutsideProject(ref m, ref p) => write!(
+                f,
+                "not removing anything, as {} lists {:?}, which is outside the project",
+                m, p
+            ),
+            PiError::Exists(ref p) => write!(
+                f,
+                "Path '{}' already exists. Rerun with -f or --force to overwrite, or --merge to add missing files.",
+                p
+            ),
+            PiError::UnknownArchive(ref p) => write!(
+                f,
+                "archive name {:?} must end in .tar, .tar.gz, .tgz, or .zip",
+                p
+            ),
+            PiError::CreateFile(ref p, ref e) => write!(
+                f,
+                "Failed to create file: {:?} ({}). Check that the directory is included in your template.toml",
+                p, e
+            ),
+            PiError::Stage(ref p, ref e) => {
+                write!(f, "could not create a directory next to '{}': {}", p, e)
+            }
+            PiError::Commit(ref p, ref e) => {
+                write!(f, "could not move project into '{}': {}", p, e)
+            }
+            PiError::Vcs(ref vc, ref e) => write!(f, "{} failed to initialize: {}", vc, e),
+        }
+    }
+}
+
+impl Error for PiError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match *self {
+            PiError::Io(ref e)
+            | PiError::CreateFile(_, ref e)
+            | PiError::Stage(_, ref e)
+            | PiError::Commit(_, ref e)
+            | PiError::Vcs(_, ref e)
+            | PiError::Unpack(_, ref e)
+            | PiError::VcsClone(_, ref e) => Some(e),
+            PiError::Parse(_, ref e) => Some(e),
+            PiError::Clone(_, ref e) | PiError::Revision(_, ref e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PiError {
+    fn from(e: io::Error) -> PiError {
+        PiError::Io(e)
+    }
+}
@@ -0,0 +1,299 @@
+//! Module containing the filesystems that projects are rendered into.
+
+use archive::Entry;
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::prelude::*;
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Where rendered files and directories end up. Paths are `/`-separated.
+pub trait Filesystem {
+    /// Create the directory `path`. Fails if it already exists, or if its
+    /// parent doesn't.
+    fn create_dir(&mut self, path: &str) -> io::Result<()>;
+    /// Create or truncate the file at `path` and write `contents` to it.
+    /// Fails if the directory it goes in doesn't exist.
+    fn write_file(&mut self, path: &str, contents: &[u8]) -> io::Result<()>;
+    /// Read the whole file at `path`.
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>>;
+    /// Whether a file or directory exists at `path`.
+    fn exists(&self, path: &str) -> bool;
+    /// Whether a regular file exists at `path`.
+    fn is_file(&self, path: &str) -> bool;
+    /// Move the file at `from` to `to`.
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()>;
+    /// Mark the file at `path` as executable.
+    fn set_executable(&mut self, path: &str) -> io::Result<()>;
+    /// Unix permission bits of the file at `path`.
+    fn mode(&self, path: &str) -> io::Result<u32>;
+    /// Whether this is the real filesystem. Only projects on disk are
+    /// rendered into a temporary directory and moved into place at the end.
+    fn is_disk(&self) -> bool {
+        false
+    }
+}
+
+/// The real filesystem.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Disk;
+
+impl Filesystem for Disk {
+    fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn write_file(&mut self, path: &str, contents: &[u8]) -> io::Result<()> {
+        File::create(path)?.write_all(contents)
+    }
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        Path::new(path).exists()
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        Path::new(path).is_file()
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        fs::rename(from, to)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn set_executable(&mut self, path: &str) -> io::Result<()> {
+        let mut p = fs::metadata(path)?.permissions();
+        p.set_mode(0o755);
+        fs::set_permissions(path, p)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn set_executable(&mut self, _: &str) -> io::Result<()> {
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn mode(&self, path: &str) -> io::Result<u32> {
+        Ok(fs::metadata(path)?.permissions().mode() & 0o7777)
+    }
+
+    #[cfg(target_os = "windows")]
+    fn mode(&self, path: &str) -> io::Result<u32> {
+        if fs::metadata(path)?.permissions().readonly() {
+            Ok(0o444)
+        } else {
+            Ok(0o644)
+        }
+    }
+
+    fn is_disk(&self) -> bool {
+        true
+    }
+}
+
+/// A filesystem held in memory. Clones share the same contents, so keep one
+/// around to look at what was rendered.
+#[derive(Debug, Clone, Default)]
+pub struct Memory {
+    entries: Arc<Mutex<BTreeMap<String, Entry>>>,
+}
+
+impl Memory {
+    /// Create an empty filesystem.
+    pub fn new() -> Memory {
+        Memory::default()
+    }
+
+    /// Everything written so far, keyed by path.
+    pub fn entries(&self) -> BTreeMap<String, Entry> {
+        self.lock().clone()
+    }
+
+    fn lock(&self) -> ::std::sync::MutexGuard<'_, BTreeMap<String, Entry>> {
+        // a panic while holding the lock can't leave the map half-updated
+        self.entries.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+fn not_found(path: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("{} not found", path))
+}
+
+// As on disk, a file or directory can only go in a directory that's been
+// created.
+fn check_parent(entries: &BTreeMap<String, Entry>, path: &str) -> io::Result<()> {
+    match path.rfind('/') {
+        Some(i) if !matches!(entries.get(&path[..i]), Some(&Entry::Dir)) => {
+            Err(not_found(&path[..i]))
+        }
+        _ => Ok(()),
+    }
+}
+
+impl Filesystem for Memory {
+    fn create_dir(&mut self, path: &str) -> io::Result<()> {
+        if self.exists(path) {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path),
+            ));
+        }
+        let mut entries = self.lock();
+        check_parent(&entries, path)?;
+        entries.insert(path.to_string(), Entry::Dir);
+        Ok(())
+    }
+
+    fn write_file(&mut self, path: &str, contents: &[u8]) -> io::Result<()> {
+        let mut entries = self.lock();
+        let mode = match entries.get(path) {
+            Some(&Entry::File { mode, .. }) => mode,
+            Some(&Entry::Dir) => {
+                return Err(io::Error::other(format!("{} is a directory", path)));
+            }
+            None => {
+                check_parent(&entries, path)?;
+                0o644
+            }
+        };
+        let contents = contents.to_vec();
+        entries.insert(path.to_string(), Entry::File { contents, mode });
+        Ok(())
+    }
+
+    fn read_file(&self, path: &str) -> io::Result<Vec<u8>> {
+        match self.lock().get(path) {
+            Some(Entry::File { contents, .. }) => Ok(contents.clone()),
+            _ => Err(not_found(path)),
+        }
+    }
+
+    fn exists(&self, path: &str) -> bool {
+        // directories exist implicitly once something has been put in them
+        let mut prefix = path.trim_end_matches('/').to_string();
+        prefix.push('/');
+        let entries = self.lock();
+        entries.contains_key(path)
+            || entries
+                .range(prefix.clone()..)
+                .next()
+                .map(|(k, _)| k.starts_with(&prefix))
+                .unwrap_or(false)
+    }
+
+    fn is_file(&self, path: &str) -> bool {
+        matches!(self.lock().get(path), Some(&Entry::File { .. }))
+    }
+
+    fn rename(&mut self, from: &str, to: &str) -> io::Result<()> {
+        let mut entries = self.lock();
+        check_parent(&entries, to)?;
+        let entry = entries.remove(from).ok_or_else(|| not_found(from))?;
+        entries.insert(to.to_string(), entry);
+        Ok(())
+    }
+
+    fn set_executable(&mut self, path: &str) -> io::Result<()> {
+        match self.lock().get_mut(path) {
+            Some(Entry::File { mode, .. }) => {
+                *mode = 0o755;
+                Ok(())
+            }
+            _ => Err(not_found(path)),
+        }
+    }
+
+    fn mode(&self, path: &str) -> io::Result<u32> {
+        match self.lock().get(path) {
+            Some(&Entry::File { mode, .. }) => Ok(mode),
+            _ => Err(not_found(path)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn memory_writes_into_created_directories() {
+        let mut m = Memory::new();
+        m.create_dir("p").unwrap();
+        m.create_dir("p/src").unwrap();
+        m.write_file("p/src/main.rs", b"fn main() {}").unwrap();
+        assert!(m.is_file("p/src/main.rs"));
+        assert!(m.exists("p/src"));
+        assert!(!m.is_file("p/src"));
+        assert_eq!(m.read_file("p/src/main.rs").unwrap(), b"fn main() {}");
+        assert_eq!(m.mode("p/src/main.rs").unwrap(), 0o644);
+    }
+
+    #[test]
+    fn memory_needs_the_parent_directory() {
+        let mut m = Memory::new();
+        let e = m.write_file("p/a", b"").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        let e = m.create_dir("p/src").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        m.create_dir("p").unwrap();
+        m.write_file("p/a", b"").unwrap();
+        // a file isn't a directory
+        let e = m.write_file("p/a/b", b"").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        let e = m.rename("p/a", "q/a").unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::NotFound);
+        assert!(m.is_file("p/a"));
+    }
+
+    #[test]
+    fn memory_create_dir_fails_if_it_exists() {
+        let mut m = Memory::new();
+        m.create_dir("p").unwrap();
+        m.write_file("p/a", b"").unwrap();
+        assert_eq!(
+            m.create_dir("p").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert_eq!(
+            m.create_dir("p/a").unwrap_err().kind(),
+            io::ErrorKind::AlreadyExists
+        );
+        assert!(m.write_file("p", b"").is_err());
+    }
+
+    #[test]
+    fn memory_overwrite_keeps_the_mode() {
+        let mut m = Memory::new();
+        m.create_dir("p").unwrap();
+        m.write_file("p/run.sh", b"1").unwrap();
+        m.set_executable("p/run.sh").unwrap();
+        m.write_file("p/run.sh", b"2").unwrap();
+        assert_eq!(m.mode("p/run.sh").unwrap(), 0o755);
+        assert_eq!(m.read_file("p/run.sh").unwrap(), b"2");
+        assert!(m.set_executable("p/missing").is_err());
+    }
+
+    #[test]
+    fn memory_rename_and_clones_share_contents() {
+        let mut m = Memory::new();
+        let seen = m.clone();
+        m.create_dir("p").unwrap();
+        m.write_file("p/a", b"x").unwrap();
+        m.rename("p/a", "p/a.bak").unwrap();
+        assert!(!m.exists("p/a"));
+        assert!(seen.is_file("p/a.bak"));
+        assert_eq!(
+            m.rename("p/a", "p/b").unwrap_err().kind(),
+            io::ErrorKind::NotFound
+        );
+        let names: Vec<String> = seen.entries().keys().cloned().collect();
+        assert_eq!(names, vec!["p".to_string(), "p/a.bak".to_string()]);
+    }
+}
@@ -0,0 +1,1039 @@
+//! Module containing the `Generator` builder, the entry point for generating
+//! projects from Rust code.
+extern crate dirs;
+
+use answers;
+use answers::Answers;
+use case::*;
+use catalog;
+use colored::*;
+use error::PiError;
+use filesystem::Filesystem;
+use git2::Repository;
+use heck::*;
+use manifest;
+use render::*;
+use rustache::{HashBuilder, VecBuilder};
+use serde_json;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+use time;
+use toml::Value::Table;
+use types::*;
+use {
+    archive, cache, includes, license_or_default, project_name, read_toml_dir, read_toml_str,
+    remote, repo, templates,
+};
+
+/// Where a template comes from.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// One of the templates built into pi, e.g. `rust`.
+    Builtin(String),
+    /// A directory containing a `template.toml`, either relative to the
+    /// current directory or in one of the template directories (see
+    /// `templates::search_path`).
+    Dir(String),
+    /// A git repository with a `template.toml` at its root.
+    Git(String),
+    /// A mercurial repository, cloned with `hg`.
+    Hg(String),
+    /// A darcs repository, cloned with `darcs`.
+    Darcs(String),
+    /// A pijul repository, cloned with `pijul`.
+    Pijul(String),
+    /// A tar, gzipped tar, or zip archive of a template directory, given as a
+    /// path or an HTTP(S) URL.
+    Archive(String),
+}
+
+impl Source {
+    fn kind(&self) -> &'static str {
+        match *self {
+            Source::Builtin(_) => "builtin",
+            Source::Dir(_) => "dir",
+            Source::Git(_) => "git",
+            Source::Hg(_) => "hg",
+            Source::Darcs(_) => "darcs",
+            Source::Pijul(_) => "pijul",
+            Source::Archive(_) => "archive",
+        }
+    }
+
+    // Whether this is a repository that's cloned, which can hold several
+    // templates.
+    fn is_repository(&self) -> bool {
+        matches!(
+            *self,
+            Source::Git(_) | Source::Hg(_) | Source::Darcs(_) | Source::Pijul(_)
+        )
+    }
+
+    fn location(&self) -> &str {
+        match *self {
+            Source::Builtin(ref s)
+            | Source::Dir(ref s)
+            | Source::Git(ref s)
+            | Source::Hg(ref s)
+            | Source::Darcs(ref s)
+            | Source::Pijul(ref s)
+            | Source::Archive(ref s) => s,
+        }
+    }
+}
+
+/// What happened with version control.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum VcsStatus {
+    /// Version control wasn't requested.
+    #[default]
+    None,
+    /// The project was initialized with the given tool.
+    Initialized(String),
+    /// The given tool was requested, but the project wasn't written to disk,
+    /// or went into a directory that was already there.
+    Skipped(String),
+}
+
+/// What a call to `Generator::generate` did.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    /// The project directory, or the archive it was written to, as a full
+    /// path unless the project was kept in memory.
+    pub output: String,
+    /// Where the template came from.
+    pub source: Option<Source>,
+    /// Commit of the template that was used, for git templates and template
+    /// directories that are repositories; pi's version for builtins.
+    pub revision: Option<String>,
+    /// Directory of the repository the template is in, if not at its root.
+    pub subdir: Option<String>,
+    /// Variables the templates were rendered with.
+    pub variables: BTreeMap<String, String>,
+    /// Name of the license that was added, if any.
+    pub license: Option<String>,
+    pub version_control: VcsStatus,
+    /// Files and directories that were written.
+    pub created: Vec<String>,
+    /// Size, mode, and hash of each file that was written.
+    pub files: Vec<Written>,
+    /// Existing files that were left alone.
+    pub skipped: Vec<String>,
+    /// Backups made of files that were overwritten.
+    pub backups: Vec<String>,
+}
+
+impl Report {
+    /// The report as pretty-printed JSON. Values of variables that look like
+    /// secrets (passwords, tokens, and so on) are masked.
+    pub fn to_json(&self) -> String {
+        let variables: serde_json::Map<String, serde_json::Value> = self
+            .variables
+            .iter()
+            .map(|(k, v)| {
+                let v = if answers::is_secret(k) {
+                    "********"
+                } else {
+                    v.as_str()
+                };
+                (k.clone(), json!(v))
+            })
+            .collect();
+        let files: Vec<serde_json::Value> = self
+            .files
+            .iter()
+            .map(|f| {
+                json!({
+                    "path": f.path,
+                    "size": f.size,
+                    "mode": format!("{:o}", f.mode),
+                    "sha256": f.sha256,
+                })
+            })
+            .collect();
+        let version_control = match self.version_control {
+            VcsStatus::None => serde_json::Value::Null,
+            VcsStatus::Initialized(ref vc) => json!({ "tool": vc, "status": "initialized" }),
+            VcsStatus::Skipped(ref vc) => json!({ "tool": vc, "status": "skipped" }),
+        };
+        let source = self.source.as_ref().map(|s| {
+            json!({
+                "kind": s.kind(),
+                "location": s.location(),
+                "revision": self.revision,
+                "subdir": self.subdir,
+            })
+        });
+        let report = json!({
+            "output": self.output,
+            "source": source,
+            "variables": variables,
+            "license": self.license,
+            "version_control": version_control,
+            "files": files,
+            "skipped": self.skipped,
+            "backups": self.backups,
+        });
+        serde_json::to_string_pretty(&report).unwrap_or_default()
+    }
+
+    /// Print the files we skipped or backed up, if any.
+    pub fn summary(&self) {
+        for s in &self.skipped {
+            println!("{}: left existing file {} untouched", "Skipped".yellow(), s);
+        }
+        for b in &self.backups {
+            println!("{}: previous contents saved to {}", "Backup".cyan(), b);
+        }
+    }
+}
+
+/// Builder for generating a project from a template.
+///
+/// ```no_run
+/// use project_init::generator::{Generator, Source};
+///
+/// let report = Generator::new(Source::Builtin("rust".to_string()), "my-project")
+///     .variable("description", "A new project")
+///     .license("MIT")
+///     .generate()
+///     .unwrap();
+/// println!("created {} files", report.created.len());
+/// ```
+pub struct Generator {
+    source: Source,
+    name: String,
+    config: Config,
+    author: Option<Author>,
+    variables: Vec<(String, String)>,
+    license: Option<String>,
+    version_control: Option<String>,
+    force: Option<Force>,
+    diff: bool,
+    stderr: bool,
+    merge: bool,
+    text: TextOptions,
+    archive: Option<String>,
+    fs: Option<Box<dyn Filesystem>>,
+    home: Option<PathBuf>,
+    date: Option<(i32, String)>,
+    revision: Option<String>,
+    subdir: Option<String>,
+    template: Option<String>,
+    skip_version_control: bool,
+    offline: bool,
+}
+
+impl Generator {
+    /// Generate a project from `source` into the directory `name`.
+    pub fn new(source: Source, name: &str) -> Generator {
+        Generator {
+            source,
+            name: name.to_string(),
+            config: Config {
+                version_control: None,
+                author: None,
+                license: None,
+                user: None,
+                git: None,
+                registries: None,
+            },
+            author: None,
+            variables: Vec::new(),
+            license: None,
+            version_control: None,
+            force: None,
+            diff: false,
+            stderr: false,
+            merge: false,
+            text: TextOptions::default(),
+            archive: None,
+            fs: None,
+            home: None,
+            date: None,
+            revision: None,
+            subdir: None,
+            template: None,
+            skip_version_control: false,
+            offline: false,
+        }
+    }
+
+    /// Use the global configuration, as read from `$HOME/.pi.toml`, for
+    /// anything not set on the builder.
+    pub fn config(mut self, config: Config) -> Generator {
+        self.config = config;
+        self
+    }
+
+    /// Set the author. Defaults to the author in the global configuration.
+    pub fn author(mut self, author: Author) -> Generator {
+        self.author = Some(author);
+        self
+    }
+
+    /// Set a variable for the templates, overriding any other value.
+    pub fn variable(mut self, key: &str, value: &str) -> Generator {
+        self.variables.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Use the license `license`, e.g. `MIT` or `BSD3`.
+    pub fn license(mut self, license: &str) -> Generator {
+        self.license = Some(license.to_string());
+        self
+    }
+
+    /// Initialize the project with version control, e.g. `git`.
+    pub fn version_control(mut self, vc: &str) -> Generator {
+        self.version_control = Some(vc.to_string());
+        self
+    }
+
+    /// Generate the project even if the directory exists, dealing with
+    /// existing files according to `force`.
+    pub fn force(mut self, force: Force) -> Generator {
+        self.force = Some(force);
+        self
+    }
+
+    /// Print a diff for every existing file that would change.
+    pub fn diff(mut self, diff: bool) -> Generator {
+        self.diff = diff;
+        self
+    }
+
+    /// Print diffs and prompts to stderr rather than stdout, so that stdout
+    /// only holds the report.
+    pub fn stderr(mut self, stderr: bool) -> Generator {
+        self.stderr = stderr;
+        self
+    }
+
+    /// Only add files and directories that don't exist yet.
+    pub fn merge(mut self, merge: bool) -> Generator {
+        self.merge = merge;
+        self
+    }
+
+    /// Normalize generated text files. These take precedence over the
+    /// template's own settings.
+    pub fn text(mut self, text: TextOptions) -> Generator {
+        self.text = text;
+        self
+    }
+
+    /// Write the project to the archive at `path` instead of a directory.
+    pub fn archive(mut self, path: &str) -> Generator {
+        self.archive = Some(path.to_string());
+        self
+    }
+
+    /// Render into `fs` instead of the real filesystem, e.g. a
+    /// `filesystem::Memory`.
+    pub fn filesystem(mut self, fs: Box<dyn Filesystem>) -> Generator {
+        self.fs = Some(fs);
+        self
+    }
+
+    /// Home directory to look for `.pi_templates` and other template
+    /// directories in. Defaults to the user's home directory.
+    pub fn home(mut self, home: PathBuf) -> Generator {
+        self.home = Some(home);
+        self
+    }
+
+    /// Set the year and date inserted into templates. Defaults to today.
+    pub fn date(mut self, year: i32, date: &str) -> Generator {
+        self.date = Some((year, date.to_string()));
+        self
+    }
+
+    /// Use the template as of `revision`. Git templates are checked out at
+    /// that revision, which can be a tag, a branch, or a commit; for other
+    /// templates, a warning is printed if they have changed since.
+    pub fn revision(mut self, revision: &str) -> Generator {
+        self.revision = Some(revision.to_string());
+        self
+    }
+
+    /// Use the template in the directory `subdir` of a git repository, rather
+    /// than at its root.
+    pub fn subdir(mut self, subdir: &str) -> Generator {
+        self.subdir = Some(subdir.trim_matches('/').to_string());
+        self
+    }
+
+    /// Use the template called `name` in the catalog (`templates.toml`) of a
+    /// git repository.
+    pub fn template(mut self, name: &str) -> Generator {
+        self.template = Some(name.to_string());
+        self
+    }
+
+    /// Don't initialize version control, whatever the template or
+    /// configuration asks for.
+    pub fn skip_version_control(mut self) -> Generator {
+        self.skip_version_control = true;
+        self
+    }
+
+    /// Only use git templates already in the cache, rather than fetching
+    /// them.
+    pub fn offline(mut self, offline: bool) -> Generator {
+        self.offline = offline;
+        self
+    }
+
+    /// Generate the project.
+    pub fn generate(self) -> Result<Report, PiError> {
+        let home = self
+            .home
+            .clone()
+            .or_else(dirs::home_dir)
+            .unwrap_or_else(|| PathBuf::from("."));
+
+        // find and read the template. A temporary clone, if the cache can't be
+        // used, is removed when `_clone` goes out of scope, and an unpacked
+        // archive or a clone made by another tool when `_unpacked` does.
+        let mut _clone = None;
+        let mut _unpacked = None;
+        let mut subdir = self.subdir.clone().filter(|_| self.source.is_repository());
+        let (parsed_toml, project, builtin, revision) = match self.source {
+            Source::Builtin(ref b) => {
+                let lower = b.to_lowercase();
+                let toml_file = builtin_template(&lower)
+                    .ok_or_else(|| PiError::UnknownBuiltin(b.to_string()))?;
+                (
+                    read_toml_str(toml_file, "BUILTIN")?,
+                    String::new(),
+                    Some(lower),
+                    Some(env!("CARGO_PKG_VERSION").to_string()),
+                )
+            }
+            Source::Dir(ref d) => {
+                let mut template_path = d.to_string();
+                template_path.push_str("/template.toml");
+                let search = templates::search_path(&home);
+                let (parsed_toml, found_in) = read_toml_dir(&template_path, &search)?;
+                let project = match found_in {
+                    Some(dir) => dir.join(d).to_string_lossy().to_string(),
+                    None => d.to_string(),
+                };
+                let revision = revision(Path::new(&project));
+                (parsed_toml, project, None, revision)
+            }
+            Source::Git(ref url) => {
+                let git = self.config.git.clone().unwrap_or_default();
+                let rev = self.revision.as_deref();
+                // a template picked from the repository's catalog
+                if let Some(ref name) = self.template {
+                    let entries = catalog::fetch(url, rev, self.offline, &git)?;
+                    subdir = Some(catalog::find(&entries, name)?.path.clone());
+                }
+                // a template in a subdirectory is all that's checked out
+                let dir = cache::fetch(url, rev, subdir.as_deref(), self.offline, &git)?;
+                let (parsed_toml, project) = read_checkout(url, dir.path(), subdir.as_deref())?;
+                let revision = revision(dir.path());
+                _clone = Some(dir);
+                (parsed_toml, project, None, revision)
+            }
+            Source::Hg(ref url) | Source::Darcs(ref url) | Source::Pijul(ref url) => {
+                let vc = self.source.kind();
+                let dir = TempDir::new("pi-template")?;
+                // the tools want to create the directory themselves
+                let checkout = dir.path().join("repo");
+                repo::clone(vc, url, self.revision.as_deref(), &checkout)
+                    .map_err(|e| PiError::VcsClone(url.to_string(), e))?;
+                // a template picked from the repository's catalog
+                if let Some(ref name) = self.template {
+                    let entries = catalog::read(&checkout)?
+                        .ok_or_else(|| PiError::NoCatalog(url.to_string()))?;
+                    subdir = Some(catalog::find(&entries, name)?.path.clone());
+                }
+                let (parsed_toml, project) = read_checkout(url, &checkout, subdir.as_deref())?;
+                let revision = repo::revision(vc, &checkout);
+                _unpacked = Some(dir);
+                (parsed_toml, project, None, revision)
+            }
+            Source::Archive(ref location) => {
+                let dir = TempDir::new("pi-template")?;
+                let contents = archive::fetch(location, dir.path())?;
+                let project = archive::root(dir.path()).to_string_lossy().to_string();
+                let mut template_path = project.clone();
+                template_path.push_str("/template.toml");
+                let (parsed_toml, _) = read_toml_dir(&template_path, &[])?;
+                _unpacked = Some(dir);
+                // archives aren't versioned, but their contents identify them
+                (
+                    parsed_toml,
+                    project,
+                    None,
+                    Some(manifest::sha256(&contents)),
+                )
+            }
+        };
+        if let (Some(wanted), Some(found)) = (self.revision.as_ref(), revision.as_ref()) {
+            // repositories are checked out at the revision asked for
+            if wanted != found && !self.source.is_repository() {
+                eprintln!(
+                    "{}: template {} is at revision {}, not {}",
+                    "Warning".yellow(),
+                    self.source.location(),
+                    found,
+                    wanted
+                );
+            }
+        }
+        let parsed_dirs = parsed_toml.files;
+        let parsed_config = parsed_toml.config;
+        let config = self.config;
+
+        // set license if it's set. An explicit license wins; after that,
+        // builtins prefer the global license and other templates their own.
+        let license = if builtin.is_some() {
+            config.license.or(parsed_toml.license)
+        } else {
+            parsed_toml.license.or(config.license)
+        };
+        let license = self.license.or(license);
+        let (license_contents, license_name) = match license {
+            Some(ref l) => {
+                let (contents, name) = license_or_default(l);
+                (Some(contents), name)
+            }
+            None => (None, ""),
+        };
+
+        // set version
+        let version = if let Some(config) = parsed_config.clone() {
+            if let Some(v) = config.version {
+                v
+            } else {
+                "0.1.0".to_string()
+            }
+        } else {
+            eprintln!(
+                "{}: no version info found, defaulting to '0.1.0'",
+                "Warning".yellow()
+            );
+            "0.1.0".to_string()
+        };
+
+        // set line endings etc. for generated files
+        let template_text = if let Some(ref config) = parsed_config {
+            let line_endings = config.line_endings.as_ref().and_then(|l| match l.parse() {
+                Ok(le) => Some(le),
+                Err(e) => {
+                    eprintln!(
+                        "{}: {}, leaving line endings as they are",
+                        "Warning".yellow(),
+                        e
+                    );
+                    None
+                }
+            });
+            TextOptions {
+                line_endings,
+                trailing_newline: config.trailing_newline,
+                bom: config.bom,
+            }
+        } else {
+            TextOptions::default()
+        };
+
+        // set github username to null if it's not provided
+        let author = match self.author.or(config.author) {
+            Some(a) => a,
+            None => Author {
+                name: String::new(),
+                email: String::new(),
+                github_username: None,
+            },
+        };
+        let github_username = if let Some(uname) = author.github_username {
+            uname
+        } else {
+            eprintln!(
+                "{}: no github username found, defaulting to null",
+                "Warning".yellow()
+            );
+            "".to_string()
+        };
+
+        let (year, current_date) = match self.date {
+            Some(d) => d,
+            None => {
+                let now = time::now();
+                let date = time::strftime("%m-%d-%Y", &now).unwrap_or_default();
+                (now.tm_year + 1900, date)
+            }
+        };
+
+        // Collect the variables for the templates; later ones win.
+        let mut variables = BTreeMap::new();
+        // project-specific, then global
+        for user in vec![parsed_toml.user, config.user].into_iter().flatten() {
+            if let Table(t) = user.toml {
+                for (key, value) in &t {
+                    if let Some(a) = value.as_str() {
+                        variables.insert(key.to_string(), a.to_string());
+                    }
+                }
+            }
+        }
+        // add the normal stuff
+        let name = self.name.as_str();
+        let project_name = project_name(name);
+        variables.insert("project".to_string(), project_name.clone());
+        variables.insert("Project".to_string(), project_name.to_capitalized());
+        variables.insert("ProjectCamelCase".to_string(), project_name.to_camel_case());
+        variables.insert("year".to_string(), year.to_string());
+        variables.insert("name".to_string(), author.name);
+        variables.insert("version".to_string(), version);
+        variables.insert("email".to_string(), author.email);
+        variables.insert("github_username".to_string(), github_username);
+        variables.insert("license".to_string(), license_name.to_string());
+        variables.insert("date".to_string(), current_date);
+        for (key, value) in &self.variables {
+            variables.insert(key.to_string(), value.to_string());
+        }
+
+        // Make a hash for inserting stuff into templates.
+        let mut hash = HashBuilder::new();
+        for (key, value) in &variables {
+            hash = hash.insert(key, value.as_str());
+        }
+
+        let force = self.force.unwrap_or(Force::Overwrite);
+        let mut writer = match self.fs {
+            Some(fs) => Writer::with_filesystem(force, self.diff, fs),
+            None => Writer::new(force, self.diff),
+        };
+        writer.stderr = self.stderr;
+
+        // bail out if the directory (or archive) exists, unless we were told to overwrite or merge.
+        let output = self.archive.clone().unwrap_or_else(|| name.to_string());
+        let exists = if self.archive.is_some() {
+            Path::new(&output).exists()
+        } else {
+            writer.exists(&output)
+        };
+        if exists && self.force.is_none() && !self.merge {
+            return Err(PiError::Exists(output));
+        };
+        writer.merge = self.merge;
+        // options set on the builder take precedence over the template's
+        writer.text = self.text.or(template_text);
+        if let Some(ref a) = self.archive {
+            writer.archive(a)?;
+        }
+
+        // render into a temporary directory, which is moved into place once
+        // everything has succeeded.
+        let root = writer
+            .stage(name)
+            .map_err(|e| PiError::Stage(name.to_string(), e))?;
+
+        // create directories
+        if let Some(dirs_pre) = parsed_dirs.directories {
+            render_dirs(dirs_pre, &hash, &root, &mut writer)?;
+        }
+
+        // create a list of files contained in the project, and create those files.
+        // TODO should include templates/scripts/etc.
+        let files = if let Some(files_pre) = parsed_dirs.files {
+            render_files(files_pre, &hash, &root, &mut writer)? // FIXME files need to have a newline insert in between them?
+        } else {
+            VecBuilder::new()
+        };
+
+        // create license if it was asked for
+        if let Some(lic) = license_contents {
+            render_file(lic, &root, "LICENSE", &hash, &mut writer)?;
+        }
+
+        // render readme if requested
+        if let Some(readme) = parsed_toml.with_readme {
+            if readme {
+                render_file(includes::README, &root, "README.md", &hash, &mut writer)?;
+            }
+        }
+
+        if let Some(ref template_str) = builtin {
+            // render appropriate stuff by name.
+            let hash_with_files = HashBuilder::new().insert("files", files);
+            // file names follow the project variable, which may have been overridden
+            render_builtin(
+                template_str,
+                &variables["project"],
+                &root,
+                &hash,
+                &hash_with_files,
+                &mut writer,
+            )?;
+        } else {
+            // Make a hash for inserting stuff into templates.
+            hash = hash.insert("files", files);
+
+            // render templates
+            render_templates(
+                &project,
+                &root,
+                &hash,
+                parsed_dirs.templates,
+                false,
+                &mut writer,
+            )?;
+
+            // render scripts, i.e. files that should be executable.
+            render_templates(
+                &project,
+                &root,
+                &hash,
+                parsed_dirs.scripts,
+                true,
+                &mut writer,
+            )?;
+        }
+
+        // record how the project was made, so it can be made again
+        let recorded = match self.source {
+            Source::Dir(_) => {
+                // local directories are recorded in full, so replaying works from
+                // anywhere, including the one a template was found in on the search path
+                let full = Path::new(&project).canonicalize()?;
+                Source::Dir(full.to_string_lossy().to_string())
+            }
+            Source::Archive(ref a) if !remote::is_http(a) => {
+                let full = Path::new(a).canonicalize()?;
+                Source::Archive(full.to_string_lossy().to_string())
+            }
+            ref s => s.clone(),
+        };
+        let mut answers = Answers::new(&recorded, revision.clone(), license, &variables);
+        answers.template.subdir = subdir.clone();
+        write_own_file(&answers.to_toml(), &root, answers::FILE, &mut writer)?;
+        // and what it consists of, so it can be undone, along with whatever an
+        // earlier run into the same directory created
+        let mut manifest = writer.manifest();
+        let earlier = Path::new(name).join(manifest::FILE);
+        if writer.on_disk() && earlier.is_file() {
+            manifest.merge(manifest::Manifest::read(&earlier.to_string_lossy())?);
+        }
+        write_own_file(&manifest.to_toml(), &root, manifest::FILE, &mut writer)?;
+
+        // initialize version control. Builtins use the global setting; other
+        // templates can pick their own.
+        let version_control = if self.skip_version_control {
+            None
+        } else if self.version_control.is_some() {
+            self.version_control
+        } else if builtin.is_some() {
+            config.version_control
+        } else if let Some(c) = parsed_config {
+            c.version_control
+        } else {
+            config.version_control
+        };
+        let vcs = match version_control {
+            Some(vc) => {
+                if !writer.on_disk() {
+                    eprintln!(
+                        "{}: not initializing {}, as the project is not being written to disk",
+                        "Warning".yellow(),
+                        vc
+                    );
+                    VcsStatus::Skipped(vc)
+                } else if !writer.manifest().created_directory {
+                    // a directory that's already there may be a repository,
+                    // or hold files that aren't the template's
+                    VcsStatus::Skipped(vc)
+                } else {
+                    VcsStatus::Initialized(vc)
+                }
+            }
+            None => VcsStatus::None,
+        };
+        // the repository is set up before the project is moved into place
+        if let VcsStatus::Initialized(ref vc) = vcs {
+            repo::init(vc, &root).map_err(|e| PiError::Vcs(vc.clone(), e))?;
+        }
+
+        // move the finished project into place
+        writer
+            .commit()
+            .map_err(|e| PiError::Commit(output.clone(), e))?;
+        // in full, so the report says where the project is wherever it's read
+        let on_disk = writer.on_disk() || self.archive.is_some();
+        let output = match Path::new(&output).canonicalize() {
+            Ok(full) if on_disk => full.to_string_lossy().to_string(),
+            _ => output,
+        };
+
+        Ok(Report {
+            output,
+            source: Some(self.source),
+            revision,
+            subdir,
+            variables,
+            license: if license_name.is_empty() {
+                None
+            } else {
+                Some(license_name.to_string())
+            },
+            version_control: vcs,
+            created: writer.created,
+            files: writer.files,
+            skipped: writer.skipped,
+            backups: writer.backups,
+        })
+    }
+}
+
+// Read the template in the repository checked out at `dir`, from `subdir` if
+// given. Returns it along with the directory it's in.
+fn read_checkout(
+    url: &str,
+    dir: &Path,
+    subdir: Option<&str>,
+) -> Result<(Project, String), PiError> {
+    let mut project = dir.to_string_lossy().to_string();
+    if let Some(subdir) = subdir {
+        project.push('/');
+        project.push_str(subdir);
+    } else if !dir.join("template.toml").exists() {
+        // the repository may hold several templates instead
+        if let Some(entries) = catalog::read(dir)? {
+            let names = entries.into_iter().map(|e| e.name).collect();
+            return Err(PiError::ChooseTemplate(url.to_string(), names));
+        }
+    }
+    let mut template_path = project.clone();
+    template_path.push_str("/template.toml");
+    let (parsed_toml, _) = read_toml_dir(&template_path, &[])?;
+    Ok((parsed_toml, project))
+}
+
+// The commit checked out in the repository at `path`, if it is one.
+fn revision(path: &Path) -> Option<String> {
+    let repo = Repository::open(path).ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id().to_string())
+}
+
+/// The `template.toml` of a builtin template.
+pub fn builtin_template(name: &str) -> Option<&'static str> {
+    match name {
+        "rust" => Some(includes::RUST_TEMPLATE),
+        "vim" | "vimscript" => Some(includes::VIM_TEMPLATE),
+        "python" => Some(includes::PY_TEMPLATE),
+        "haskell" | "kmett" => Some(includes::HASK_TEMPLATE),
+        "mad" | "madlang" => Some(includes::MADLANG_TEMPLATE),
+        "idris" => Some(includes::IDRIS_TEMPLATE),
+        "julia" => Some(includes::JULIA_TEMPLATE),
+        "miso" => Some(includes::MISO_TEMPLATE),
+        "plain" => Some(includes::PLAIN_TEMPLATE),
+        "ats" => Some(includes::ATS_TEMPLATE),
+        _ => None,
+    }
+}
+
+// Write the files that are specific to one of the builtin templates.
+fn render_builtin(
+    template_str: &str,
+    name: &str,
+    root: &str,
+    hash: &HashBuilder,
+    hash_with_files: &HashBuilder,
+    writer: &mut Writer,
+) -> Result<(), PiError> {
+    match template_str {
+        "plain" => (),
+
+        "rust" => {
+            let mut bench_path = "benches/".to_string();
+            bench_path.push_str(name);
+            bench_path.push_str(".rs");
+            write_file_plain(includes::RUST_LIB, root, "src/lib.rs", writer)?;
+            write_file_plain(includes::RUST_MAIN, root, "src/main.rs", writer)?;
+            write_file_plain(includes::RUST_TRAVIS_CI, root, ".travis.yml", writer)?;
+            write_file_plain(includes::RUST_GITIGNORE, root, ".gitignore", writer)?;
+            write_file_plain(includes::RUST_BENCHMARKS, root, &bench_path, writer)?;
+            render_file(includes::CARGO_TOML, root, "Cargo.toml", hash, writer)?;
+        }
+
+        "vim" | "vimscript" => {
+            write_file_plain(includes::VIM_GITIGNORE, root, ".gitignore", writer)?;
+            render_file(
+                includes::VIM_TRAVIS,
+                root,
+                ".travis.yml",
+                hash_with_files,
+                writer,
+            )?;
+            render_file(
+                includes::VIMBALL,
+                root,
+                "vimball.txt",
+                hash_with_files,
+                writer,
+            )?;
+        }
+
+        "python" => {
+            render_file(includes::PY_SETUP, root, "setup.py", hash, writer)?;
+            write_file_plain(includes::PY_CFG, root, "setup.cfg", writer)?;
+            write_file_plain(includes::PY_GITIGNORE, root, ".gitignore", writer)?;
+            let mut bin_path = "bin/".to_string();
+            bin_path.push_str(name);
+            render_file(includes::PY_BIN, root, &bin_path, hash, writer)?;
+        }
+
+        "miso" => {
+            write_file_plain(includes::MISO_SETUP_HS, root, "Setup.hs", writer)?;
+            write_file_plain(includes::MISO_MAIN, root, "app/Main.hs", writer)?;
+            write_file_plain(includes::MISO_LIB, root, "src/Lib.hs", writer)?;
+            let mut cabal_path = name.to_string();
+            cabal_path.push_str(".cabal");
+            render_file(includes::MISO_CABAL, root, &cabal_path, hash, writer)?;
+            write_file_plain(includes::MISO_GITIGNORE, root, ".gitignore", writer)?;
+            render_file(includes::MISO_STACK, root, "stack.yaml", hash, writer)?;
+            write_file_plain(includes::HLINT_TEMPLATE, root, ".hlint.yaml", writer)?;
+            write_file_plain(includes::SHAKE_STACK, root, "stack-shake.yaml", writer)?;
+            write_file_plain(includes::MISO_TRAVIS, root, ".travis.yml", writer)?;
+            let shake = render_file(includes::MISO_SHAKE, root, "shake.hs", hash, writer)?;
+            render_file(
+                includes::MISO_HTML,
+                root,
+                "web-src/index.html",
+                hash,
+                writer,
+            )?;
+            write_file_plain(includes::HASKELL_TRAVIS_CI, root, ".travis.yml", writer)?;
+            write_file_plain(
+                includes::STYLISH_HASKELL,
+                root,
+                ".stylish-haskell.yaml",
+                writer,
+            )?;
+            // only a new copy needs to be made executable
+            if shake == Outcome::Written {
+                let mut shake_path = root.to_string();
+                shake_path.push_str("/shake.hs");
+                writer
+                    .set_executable(&shake_path)
+                    .map_err(|e| writer.create_error(&shake_path, e))?;
+            }
+        }
+
+        "madlang" | "mad" => {
+            let mut src_path = "src/".to_string();
+            src_path.push_str(name);
+            src_path.push_str(".mad");
+            render_file(includes::MADLANG_SRC, root, &src_path, hash, writer)?;
+        }
+
+        "idris" => {
+            let mut pkg_path = name.to_string();
+            pkg_path.push_str(".ipkg");
+            write_file_plain(includes::IDRIS_GITIGNORE, root, ".gitignore", writer)?;
+            write_file_plain(includes::IDRIS_CTAGS, root, ".ctags", writer)?;
+            let mut main_path = name.to_capitalized();
+            main_path.push_str(".idr");
+            render_file(includes::IPKG, root, &pkg_path, hash, writer)?;
+            render_file(includes::IPKG_TEST, root, "test.ipkg", hash, writer)?;
+            // render_file(includes::IDRIS_EXE, root, &main_path, hash, writer)?;
+            render_file(
+                includes::IDRIS_TEST,
+                root,
+                "src/Test/Spec.idr",
+                hash,
+                writer,
+            )?;
+            let mut lib_path = "src/".to_string();
+            lib_path.push_str(&name.to_capitalized());
+            lib_path.push('/');
+            lib_path.push_str("Lib.idr");
+            render_file(includes::IDRIS_LIB, root, &lib_path, hash, writer)?;
+        }
+
+        "julia" => {
+            write_file_plain(includes::JULIA_REQUIRE, root, "REQUIRE", writer)?;
+            let mut project_path = "src/".to_string();
+            project_path.push_str(name.to_capitalized().as_str());
+            project_path.push_str(".jl");
+            write_file_plain(includes::JULIA_GITIGNORE, root, ".gitignore", writer)?;
+            write_file_plain(includes::JULIA_SRC, root, &project_path, writer)?;
+            write_file_plain(includes::JULIA_TEST, root, "test/test.jl", writer)?;
+        }
+
+        "ats" => {
+            write_file_plain(includes::ATS_CTAGS, root, ".ctags", writer)?;
+            let mut src_path = "src/".to_string();
+            src_path.push_str(name);
+            src_path.push_str(".dats");
+            render_file(includes::ATS_SRC, root, &src_path, hash, writer)?;
+            write_file_plain(includes::ATS_FORMAT, root, ".atsfmt.toml", writer)?;
+            write_file_plain(includes::ATS_TRAVIS, root, ".clang-format", writer)?;
+            render_file(includes::ATS_PKG, root, "atspkg.dhall", hash, writer)?;
+            render_file(includes::ATS_LIB, root, "pkg.dhall", hash, writer)?;
+            render_file(includes::ATS_TRAVIS, root, ".travis.yml", hash, writer)?;
+            render_file(includes::ATS_GITIGNORE, root, ".gitignore", hash, writer)?;
+        }
+
+        "haskell" | "kmett" => {
+            write_file_plain(includes::SETUP_HS, root, "Setup.hs", writer)?;
+            write_file_plain(includes::MAIN, root, "app/Main.hs", writer)?;
+            render_file(includes::LIB, root, "src/Lib.hs", hash, writer)?;
+            write_file_plain(includes::BENCH, root, "bench/Bench.hs", writer)?;
+            write_file_plain(includes::TEST, root, "test/Spec.hs", writer)?;
+            write_file_plain(includes::HLINT_TEMPLATE, root, ".hlint.yaml", writer)?;
+            write_file_plain(
+                includes::STYLISH_HASKELL,
+                root,
+                ".stylish-haskell.yaml",
+                writer,
+            )?;
+            render_file(includes::DEFAULT_NIX, root, "default.nix", hash, writer)?;
+            render_file(includes::RELEASE_NIX, root, "release.nix", hash, writer)?;
+            let mut cabal_path = name.to_string();
+            cabal_path.push_str(".cabal");
+            if template_str == "haskell" {
+                render_file(includes::CABAL, root, &cabal_path, hash, writer)?;
+            } else {
+                render_file(includes::KMETT, root, &cabal_path, hash, writer)?;
+            }
+            write_file_plain(includes::HASKELL_GITIGNORE, root, ".gitignore", writer)?;
+            write_file_plain(includes::RELEASE_NIX, root, "release.nix", writer)?;
+            write_file_plain(includes::HSPEC, root, ".hspec", writer)?;
+            write_file_plain(includes::HS_GITATTRIBUTES, root, ".gitattributes", writer)?;
+            render_file(includes::STACK_YAML, root, "stack.yaml", hash, writer)?;
+            render_file(
+                includes::CABAL_PROJECT,
+                root,
+                "cabal.project.local",
+                hash,
+                writer,
+            )?;
+            render_file(
+                includes::HASKELL_TRAVIS_CI,
+                root,
+                ".travis.yml",
+                hash,
+                writer,
+            )?;
+            render_file(
+                includes::HASKELL_APPVEYOR,
+                root,
+                "appveyor.yml",
+                hash,
+                writer,
+            )?;
+            render_file(includes::HS_CHANGELOG, root, "CHANGELOG.md", hash, writer)?;
+        }
+        _ => (),
+    };
+    Ok(())
+}
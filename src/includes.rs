@@ -10,6 +10,9 @@ pub const ALL_RIGHTS_RESERVED: &str = include_str!("includes/licenses/AllRightsR
 // README.md
 pub const README: &str = include_str!("includes/README.md");
 
+// Registry index of remote templates
+pub const REGISTRY: &str = include_str!("includes/registry.toml");
+
 // Plain template
 pub const PLAIN_TEMPLATE: &str = include_str!("includes/plain.toml");
 
@@ -4,60 +4,248 @@ extern crate clap;
 #[macro_use]
 extern crate text_io;
 
-extern crate case;
 extern crate colored;
 extern crate dirs;
-extern crate git2;
 extern crate project_init;
-extern crate rustache;
-extern crate tempdir;
 extern crate time;
 extern crate toml;
+extern crate yaml_rust;
 
-use case::*;
-use clap::{App, AppSettings};
+use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
 use colored::*;
-use git2::Repository;
+use project_init::answers::Answers;
+use project_init::error::PiError;
+use project_init::generator::{Generator, Source};
 use project_init::render::*;
 use project_init::types::*;
 use project_init::*;
-use rustache::*;
-use std::fs;
-use std::fs::set_permissions;
-use std::fs::File;
-use std::path::Path;
 use std::path::PathBuf;
 use std::process::Command;
-use tempdir::TempDir;
 use time::strftime;
+use yaml_rust::Yaml;
+
+// `--force` takes an optional conflict policy. It has to be attached with `=`,
+// otherwise `pi new -f rust project` would read `rust` as the policy, so a bare
+// `-f`/`--force` is expanded before clap sees it.
+fn expand_force(args: Vec<String>) -> Vec<String> {
+    let takes_policy = !matches!(
+        args.get(1).map(|s| s.as_str()),
+        Some("update") | Some("u") | None
+    );
+    args.into_iter()
+        .map(|a| {
+            if takes_policy && (a == "-f" || a == "--force") {
+                "--force=overwrite".to_string()
+            } else {
+                a
+            }
+        })
+        .collect()
+}
 
-#[cfg(not(target_os = "windows"))]
-use std::os::unix::fs::PermissionsExt;
+// Conflict policy requested on the command line, if any.
+fn force_policy(matches: &ArgMatches) -> Option<Force> {
+    matches
+        .value_of("force")
+        .map(|f| f.parse().expect("Clap failed to validate conflict policy"))
+}
 
-#[cfg(not(target_os = "windows"))]
-fn mk_executable<P: AsRef<Path>>(p: P) {
-    let f = File::open(&p).unwrap();
-    let metadata = f.metadata().unwrap();
-    let mut permissions = metadata.permissions();
-    permissions.set_mode(0o755);
-    set_permissions(p, permissions).unwrap();
+// Text normalization requested on the command line.
+fn text_options(matches: &ArgMatches) -> TextOptions {
+    TextOptions {
+        line_endings: matches
+            .value_of("line_endings")
+            .map(|l| l.parse().expect("Clap failed to validate line endings")),
+        trailing_newline: matches.value_of("trailing_newline").map(|b| b == "true"),
+        bom: matches.value_of("bom").map(|b| b == "true"),
+    }
 }
 
-#[cfg(target_os = "windows")]
-fn mk_executable<P: AsRef<Path>>(_: P) -> () {
-    ()
+// Apply the options shared by `new`, `init`, and `git`, then generate the
+// project.
+fn generate(
+    generator: Generator,
+    matches: &ArgMatches,
+    decoded: Config,
+    author: Author,
+    year: i32,
+    current_date: &str,
+    home: PathBuf,
+) -> Result<(), PiError> {
+    let mut generator = generator
+        .config(decoded)
+        .author(author)
+        .date(year, current_date)
+        .home(home)
+        .diff(matches.is_present("diff"))
+        .stderr(matches.is_present("report"))
+        .merge(matches.is_present("merge"))
+        .offline(matches.is_present("offline"))
+        .text(text_options(matches));
+    if let Some(force) = force_policy(matches) {
+        generator = generator.force(force);
+    }
+    let archive = matches.value_of("output_archive");
+    if let Some(a) = archive {
+        generator = generator.archive(a);
+    }
+    let report = generator.generate()?;
+
+    // Print that we're done
+    if matches.value_of("report") == Some("json") {
+        println!("{}", report.to_json());
+        return Ok(());
+    }
+    report.summary();
+    if archive.is_some() {
+        println!("Finished writing project to {}", report.output);
+    } else {
+        println!("Finished initializing project in {}/", report.output);
+    }
+    Ok(())
+}
+
+// List the templates in the catalog of the repository at `url`.
+fn print_catalog(url: &str, entries: &[catalog::Entry]) {
+    let mut heading = "Templates in ".to_string();
+    heading.push_str(url);
+    heading.push(':');
+    println!("{}", heading.cyan());
+    for e in entries {
+        let mut line = e.name.clone();
+        if let Some(ref d) = e.description {
+            line.push_str(" - ");
+            line.push_str(d);
+        }
+        if !e.tags.is_empty() {
+            line.push_str(" [");
+            line.push_str(&e.tags.join(", "));
+            line.push(']');
+        }
+        println!("  - {}", line);
+    }
+}
+
+// A template from a registry index, with what's known about it.
+fn registry_entry(e: &registry::Entry) -> String {
+    let mut line = e.name.clone();
+    line.push_str(": ");
+    line.push_str(&e.source);
+    if let Some(ref d) = e.description {
+        line.push_str(" - ");
+        line.push_str(d);
+    }
+    if !e.tags.is_empty() {
+        line.push_str(" [");
+        line.push_str(&e.tags.join(", "));
+        line.push(']');
+    }
+    line
+}
+
+// A cached repository, with the part of it checked out and the revision asked
+// for, and the commit it's at.
+fn cache_entry(e: &cache::Entry) -> String {
+    let mut line = e.url.clone();
+    if let Some(ref s) = e.sparse {
+        line.push_str("//");
+        line.push_str(s);
+    }
+    if let Some(ref r) = e.revision {
+        line.push('@');
+        line.push_str(r);
+    }
+    if let Some(c) = e.commit() {
+        line.push_str(" (");
+        line.push_str(&c[..12]);
+        line.push(')');
+    }
+    line
+}
+
+// A template installed with `pi template install`, for listing.
+fn installed_template(t: &templates::Installed) -> String {
+    let mut line = t.name.clone();
+    line.push_str(": ");
+    line.push_str(&t.origin.source());
+    if let Some(ref i) = t.origin.installed {
+        line.push_str(" (");
+        line.push_str(i.get(..12).unwrap_or(i));
+        line.push(')');
+    }
+    line
+}
+
+// Exit code for an error: 2 for problems with the input, 1 for failures while
+// generating the project. Only the low byte of an exit status survives.
+fn exit_code(e: &PiError) -> i32 {
+    match *e {
+        PiError::MissingTemplate(_)
+        | PiError::Parse(_, _)
+        | PiError::InvalidLicense(_)
+        | PiError::Exists(_)
+        | PiError::UnknownArchive(_)
+        | PiError::UnknownBuiltin(_)
+        | PiError::Revision(_, _)
+        | PiError::NotCached(_)
+        | PiError::Unpack(_, _)
+        | PiError::NoProjectName(_)
+        | PiError::NotUpgradable(_)
+        | PiError::OutsideProject(_, _)
+        | PiError::UnknownTemplate(_, _)
+        | PiError::ChooseTemplate(_, _)
+        | PiError::NoCatalog(_)
+        | PiError::NameRequired(_)
+        | PiError::AlreadyInstalled(_, _)
+        | PiError::InvalidTemplateName(_)
+        | PiError::NotInstalled(_) => 2,
+        _ => 1,
+    }
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("{}: {}", "Error".red(), e);
+        std::process::exit(exit_code(&e));
+    }
+}
+
+// The subcommands that generate a project, each with the options they all
+// share.
+fn generate_subcommands(yaml: &Yaml) -> Vec<App<'_, '_>> {
+    let list = |key: &str| yaml[key].as_vec().map(|v| v.as_slice()).unwrap_or(&[]);
+    let args = |key: &str| -> Vec<Arg> {
+        list(key)
+            .iter()
+            .filter_map(|a| a.as_hash())
+            .map(Arg::from_yaml)
+            .collect()
+    };
+    let options = args("options");
+    let repository_options = args("repository_options");
+    let repositories = list("repositories").iter().map(|s| {
+        SubCommand::from_yaml(s)
+            .args(&options)
+            .args(&repository_options)
+    });
+    let others = list("subcommands")
+        .iter()
+        .map(|s| SubCommand::from_yaml(s).args(&options));
+    repositories.chain(others).collect()
 }
 
 #[allow(clippy::cognitive_complexity)]
 #[allow(clippy::print_literal)]
-fn main() {
+fn run() -> Result<(), PiError> {
     // command-line parser
     let yaml = load_yaml!("options-en.yml");
+    let generating = load_yaml!("generate-en.yml");
     let matches = App::from_yaml(yaml)
+        .subcommands(generate_subcommands(generating))
         .version(crate_version!())
         .set_term_width(80)
         .setting(AppSettings::SubcommandRequired)
-        .get_matches();
+        .get_matches_from(expand_force(std::env::args().collect()));
 
     // set path to .pi.toml
     let home = dirs::home_dir().expect("Couldn't determine home directory.");
@@ -65,15 +253,29 @@ fn main() {
     path.push(".pi.toml");
 
     // read global config file
-    let decoded: Config = read_toml_config(&path);
+    let decoded: Config = read_toml_config(&path)?;
+
+    // prompts go to stderr when stdout is kept for a report
+    let report = matches
+        .subcommand()
+        .1
+        .map(|m| m.is_present("report"))
+        .unwrap_or(false);
+    let prompt = |p: &str| {
+        if report {
+            eprintln!("{}", p);
+        } else {
+            println!("{}", p);
+        }
+    };
 
     // create author struct
     let author = if let Some(aut) = decoded.clone().author {
         aut
     } else {
-        println!("Enter your name");
+        prompt("Enter your name");
         let nam: String = read!("{}");
-        println!("Enter your email");
+        prompt("Enter your email");
         let ema: String = read!("{}");
         Author {
             name: nam,
@@ -82,6 +284,9 @@ fn main() {
         }
     };
 
+    // how to authenticate when fetching git templates
+    let git = decoded.git.clone().unwrap_or_default();
+
     // get year
     let now = time::now();
     let year = now.tm_year + 1900;
@@ -107,400 +312,314 @@ fn main() {
         let script_string = String::from_utf8(script.stderr).unwrap();
 
         println!("{}", script_string);
-    } else if matches.subcommand_matches("list").is_some() {
-        let remote = vec!["vmchale/haskell-ats", "vmchale/madlang-miso"];
+    } else if let Some(matches_list) = matches.subcommand_matches("list") {
+        // the templates in a repository's catalog
+        if let Some(repo) = matches_list.value_of("repo") {
+            let (repo, revision) = remote::split_revision(repo);
+            let url = remote::expand(repo);
+            let offline = matches_list.is_present("offline");
+            print_catalog(&url, &catalog::fetch(&url, revision, offline, &git)?);
+            return Ok(());
+        }
         let builtin = vec![
             "rust", "vim", "python", "haskell", "idris", "julia", "elm", "miso", "plain", "kmett",
             "madlang",
         ];
         println!("{}", "Remote Templates:".cyan());
-        for b in remote {
-            println!("  - {}", b);
+        for r in registry::all(&decoded) {
+            for e in &r.templates {
+                println!("  - {}: {}", e.name, e.source);
+            }
         }
         println!();
         println!("{}", "Builtin Templates:".cyan());
         for b in builtin {
             println!("  - {}", b);
         }
-        let mut p = home;
-        p.push(".pi_templates");
         println!("{}", "\nUser Templates:".cyan());
-        let iter = std::fs::read_dir(&p);
-        match iter {
-            Ok(x) => {
-                for dir in x {
-                    if let Ok(x) = dir {
-                        if x.path().is_dir()
-                            && x.file_name().to_str().map(|c| c.chars().nth(0).unwrap())
-                                != Some('.')
-                            && x.file_name().to_str().map(|c| c.chars().nth(0).unwrap())
-                                != Some('_')
-                        {
-                            println!("  - {}", x.file_name().to_string_lossy());
-                        }
-                    } else {
-                    }
-                }
+        for (name, dir) in templates::list(&templates::search_path(&home)) {
+            match templates::origin(&dir.join(&name)) {
+                Some(o) => println!("  - {} ({}, from {})", name, dir.display(), o.source()),
+                None => println!("  - {} ({})", name, dir.display()),
             }
-            _ => eprintln!("{}: Could not access {}", "Warning".yellow(), p.display()),
         }
-    } else if let Some(matches_init) = matches.subcommand_matches("git") {
-        // whether to overwrite
-        let force = matches_init.is_present("force");
-
-        // get repository name
-        let repo = matches_init
-            .value_of("repo")
-            .expect("Clap failed to supply repository name");
-
-        // get project name
-        let name = matches_init
-            .value_of("name")
-            .expect("Clap failed to supply project name");
-
-        // form the URL
-        let mut url = "https://github.com/".to_string();
-        url.push_str(repo);
-
-        // create a temporary directory to hold the template
-        let dir_name = repo.replace("/", "-");
-        let tmp_dir = TempDir::new(&dir_name);
-        let file = match tmp_dir {
-            Ok(t) => t,
-            Err(_) => {
-                eprintln!("{}: failed to create temporary directory", "Error".red());
-                std::process::exit(1)
+    } else if let Some(matches_search) = matches.subcommand_matches("search") {
+        let term = matches_search
+            .value_of("term")
+            .expect("Clap failed to supply search term");
+        let mut found = 0;
+        for r in registry::all(&decoded) {
+            let matching: Vec<&registry::Entry> =
+                r.templates.iter().filter(|e| e.matches(term)).collect();
+            if matching.is_empty() {
+                continue;
             }
-        };
-
-        // clone into the temporary directory
-        let file_path = file.path();
-        match Repository::clone(&url, file_path) {
-            Ok(_) => (),
-            Err(_) => {
-                eprintln!("{}: failed to clone repo at {}", "Error".red(), url);
-                std::process::exit(1)
+            println!("{}", format!("Templates in {}:", r.location).cyan());
+            for e in matching {
+                println!("  - {}", registry_entry(e));
+                found += 1;
             }
+        }
+        if found == 0 {
+            println!("No templates matching {} were found", term);
+        }
+    } else if let Some(matches_replay) = matches.subcommand_matches("replay") {
+        let path = matches_replay
+            .value_of("answers")
+            .expect("Clap failed to supply answers file");
+        let answers = Answers::read(path)?;
+
+        // default to the directory the project was generated into
+        let name = match matches_replay.value_of("name") {
+            Some(n) => n.to_string(),
+            None => answers
+                .variables
+                .get("project")
+                .cloned()
+                .ok_or_else(|| PiError::NoProjectName(path.to_string()))?,
         };
 
-        let string_dir = file_path.to_string_lossy().to_string();
-        let mut toml_string = string_dir.clone();
-        toml_string.push_str("/template.toml");
-
-        // get the parsed TOML file from the repo.
-        let (parsed_toml, _) = read_toml_dir(&toml_string, PathBuf::from("."));
-
-        // initialize the project
-        init_helper(
-            home,
-            &string_dir,
+        let generator = answers.generator(&name);
+        generate(
+            generator,
+            matches_replay,
             decoded,
             author,
-            name,
             year,
             &current_date,
-            force,
-            parsed_toml,
-            false,
-        )
-    } else if let Some(matches_init) = matches.subcommand_matches("new") {
-        let force: bool = matches_init.occurrences_of("force") == 1;
-
-        // get project name
-        let name = matches_init
-            .value_of("name")
-            .expect("Clap failed to supply project name");
-
-        // get project template type
-        let template_str_lower: String = matches_init
-            .value_of("template")
-            .expect("Clap failed to supply project directory")
-            .to_string()
-            .chars()
-            .map(|c| c.to_lowercase().to_string())
-            .collect::<Vec<String>>()
-            .join("");
-
-        let template_str = template_str_lower.as_str();
-
-        // read template.toml
-        let toml_file = match template_str {
-            "rust" => includes::RUST_TEMPLATE,
-            "vim" | "vimscript" => includes::VIM_TEMPLATE,
-            "python" => includes::PY_TEMPLATE,
-            "haskell" | "kmett" => includes::HASK_TEMPLATE,
-            "mad" | "madlang" => includes::MADLANG_TEMPLATE,
-            "idris" => includes::IDRIS_TEMPLATE,
-            "julia" => includes::JULIA_TEMPLATE,
-            "miso" => includes::MISO_TEMPLATE,
-            "plain" => includes::PLAIN_TEMPLATE,
-            "ats" => includes::ATS_TEMPLATE,
+            home,
+        )?;
+    } else if let Some(matches_upgrade) = matches.subcommand_matches("upgrade") {
+        let dir = matches_upgrade.value_of("directory").unwrap_or(".");
+        let revision = matches_upgrade.value_of("revision");
+        let offline = matches_upgrade.is_present("offline");
+        let upgrade = upgrade::upgrade(dir, revision, |g| {
+            g.config(decoded.clone())
+                .author(author.clone())
+                .date(year, &current_date)
+                .home(home.clone())
+                .offline(offline)
+        })?;
+
+        upgrade.summary();
+        if !upgrade.conflicts.is_empty() {
+            eprintln!(
+                "{}: fix the conflicts marked in the files above",
+                "Warning".yellow()
+            );
+            std::process::exit(1);
+        }
+        println!("Finished upgrading project in {}/", dir);
+    } else if let Some(matches_undo) = matches.subcommand_matches("undo") {
+        let dir = matches_undo.value_of("directory").unwrap_or(".");
+        let undo = manifest::undo(dir, matches_undo.is_present("keep_modified"))?;
+
+        undo.summary();
+        println!(
+            "Finished removing {} files and directories",
+            undo.removed.len()
+        );
+    } else if let Some(matches_template) = matches.subcommand_matches("template") {
+        match matches_template.subcommand() {
+            ("install", Some(m)) => {
+                let source = m
+                    .value_of("source")
+                    .expect("Clap failed to supply template source");
+                let mut origin = templates::Origin::parse(source)?;
+                if let Some(dir) = m.value_of("subdir") {
+                    origin.subdir = Some(dir.to_string());
+                }
+                // a template picked from the repository's catalog
+                if let Some(template) = m.value_of("template") {
+                    let revision = origin.revision.as_deref();
+                    let entries = catalog::fetch(&origin.location, revision, false, &git)?;
+                    origin.subdir = Some(catalog::find(&entries, template)?.path.clone());
+                }
+                let name = match m.value_of("name").or(m.value_of("template")) {
+                    Some(n) => n.to_string(),
+                    None => origin.name(),
+                };
+                let installed = templates::install(&name, &origin, &home, &git)?;
+                println!(
+                    "Finished installing {} into {}",
+                    installed_template(&installed),
+                    installed.path.display()
+                );
+            }
+            ("update", Some(m)) => {
+                let updated = templates::update(m.value_of("name"), &home, &git)?;
+                for (t, before) in &updated {
+                    if t.origin.installed.is_some() && t.origin.installed == *before {
+                        println!("{}: {}", "Up to date".green(), installed_template(t));
+                    } else {
+                        println!("{}: {}", "Updated".green(), installed_template(t));
+                    }
+                }
+                println!("Finished updating {} templates", updated.len());
+            }
+            ("remove", Some(m)) => {
+                let name = m
+                    .value_of("name")
+                    .expect("Clap failed to supply template name");
+                let removed = templates::remove(name, &home)?;
+                println!("{}: {}", "Removed".yellow(), installed_template(&removed));
+            }
             _ => {
-                println!("The requested template is not a built-in :(");
-                std::process::exit(0x0f00)
+                println!(
+                    "{}",
+                    format!(
+                        "Templates installed in {}:",
+                        templates::user_dir(&home).display()
+                    )
+                    .cyan()
+                );
+                for t in templates::installed(&home)? {
+                    println!("  - {}", installed_template(&t));
+                }
             }
+        }
+    } else if let Some(matches_cache) = matches.subcommand_matches("cache") {
+        // a repository given as on the command line, without its revision
+        let url = |m: &ArgMatches| {
+            m.value_of("repo").map(|r| {
+                let (r, _) = remote::split_revision(r);
+                remote::expand(remote::split_subdir(r).0)
+            })
         };
-        let parsed_toml = read_toml_str(toml_file, "BUILTIN");
-        let parsed_dirs = parsed_toml.files;
-        let parsed_config = parsed_toml.config;
-
-        // set license if it's set
-        let (license_contents, license_name) =
-            // prefer global license over builtin
-            if let Some(l) = decoded.license {
-                match l.as_str() {
-                    "BSD3" => (Some(includes::BSD3), "BSD3"),
-                    "BSD" => (Some(includes::BSD), "BSD"),
-                    "MIT" => (Some(includes::MIT), "MIT"),
-                    "GPL3" => (Some(includes::GPL3), "GLP3"),
-                    "AllRightsReserved" => (Some(includes::BSD3), "AllRightsReserved"),
-                    _ => { println!("{}: requested license not found. Defaulting to AllRightsReserved","Warning".yellow()) 
-                           ; (Some(includes::ALL_RIGHTS_RESERVED), "AllRightsReserved") }
+        match matches_cache.subcommand() {
+            ("clean", Some(m)) => {
+                let removed = cache::clean(url(m).as_deref())?;
+                for e in &removed {
+                    println!("{}: {}", "Removed".yellow(), cache_entry(e));
                 }
+                println!("Finished removing {} cached templates", removed.len());
             }
-            else if let Some(l) = parsed_toml.license {
-                match l.as_str() {
-                    "BSD3" => (Some(includes::BSD3), "BSD3"),
-                    "BSD" => (Some(includes::BSD), "BSD"),
-                    "MIT" => (Some(includes::MIT), "MIT"),
-                    "GPL3" => (Some(includes::GPL3), "GLP3"),
-                    "AllRightsReserved" => (Some(includes::BSD3), "AllRightsReserved"),
-                    _ => { println!("{}: requested license not found. Defaulting to AllRightsReserved","Warning".yellow()) 
-                           ; (Some(includes::ALL_RIGHTS_RESERVED), "AllRightsReserved") }
+            ("refresh", Some(m)) => {
+                let refreshed = cache::refresh(url(m).as_deref(), &git)?;
+                for e in &refreshed {
+                    println!("{}: {}", "Updated".green(), cache_entry(e));
                 }
+                println!("Finished updating {} cached templates", refreshed.len());
             }
-            else {
-                (None,"")
-            };
-
-        // set version
-        let version = if let Some(config) = parsed_config {
-            if let Some(v) = config.version {
-                v
-            } else {
-                "0.1.0".to_string()
+            _ => {
+                if let Some(dir) = cache::dir() {
+                    println!(
+                        "{}",
+                        format!("Templates cached in {}:", dir.display()).cyan()
+                    );
+                }
+                for e in cache::list()? {
+                    println!("  - {}", cache_entry(&e));
+                }
             }
-        } else {
-            println!(
-                "{}: no version info found, defaulting to '0.1.0'",
-                "Warning".yellow()
-            );
-            "0.1.0".to_string()
-        };
-
-        // set github username to null if it's not provided
-        let github_username = if let Some(uname) = author.github_username {
-            uname
-        } else {
-            println!(
-                "{}: No github username found, defaulting to null.",
-                "Warning".yellow()
-            );
-            "".to_string()
-        };
-
-        // Make a hash for inserting stuff into templates.
-        let hash = HashBuilder::new()
-            .insert("project", name)
-            .insert("Project", name.to_capitalized())
-            .insert("year", year)
-            .insert("name", author.name)
-            .insert("version", version)
-            .insert("email", author.email)
-            .insert("github_username", github_username)
-            .insert("license", license_name)
-            .insert("date", current_date);
-
-        // check if the directory exists and exit, if we haven't forced an overwrite.
-        if Path::new(name).exists() && !force {
-            println!(
-                "Path '{}' already exists. Rerun with -f or --force to overwrite.",
-                name
-            );
-            std::process::exit(0x0f00);
-        };
-
-        // create directories
-        let _ = fs::create_dir(name);
-        if let Some(dirs_pre) = parsed_dirs.directories {
-            render_dirs(dirs_pre, &hash, name);
         }
+    } else if let Some(matches_init) = matches.subcommand_matches("git") {
+        // get repository name
+        let repo = matches_init
+            .value_of("repo")
+            .expect("Clap failed to supply repository name");
 
-        // Create files.
-        let files = if let Some(files_pre) = parsed_dirs.files {
-            render_files(files_pre, &hash, name)
-        } else {
-            VecBuilder::new()
+        // form the URL, and check out the revision asked for, if any. The
+        // repository is given as REPO[//SUBDIR][@REVISION]
+        let (repo, revision) = remote::split_revision(repo);
+        let (repo, subdir) = remote::split_subdir(repo);
+        let url = remote::expand(repo);
+
+        // without a project name, list the templates in the repository
+        let name = match matches_init.value_of("name") {
+            Some(n) => n,
+            None => {
+                let offline = matches_init.is_present("offline");
+                let entries = match catalog::fetch(&url, revision, offline, &git) {
+                    Err(PiError::NoCatalog(url)) => return Err(PiError::NameRequired(url)),
+                    e => e?,
+                };
+                print_catalog(&url, &entries);
+                return Ok(());
+            }
         };
 
-        // create license if it was asked for
-        if let Some(lic) = license_contents {
-            render_file(lic, name, "LICENSE", &hash);
+        let mut generator = Generator::new(Source::Git(url), name);
+        if let Some(rev) = revision {
+            generator = generator.revision(rev);
         }
-
-        // render readme if requested
-        if let Some(readme) = parsed_toml.with_readme {
-            if readme {
-                render_file(includes::README, name, "README.md", &hash);
-            }
+        if let Some(dir) = matches_init.value_of("subdir").or(subdir) {
+            generator = generator.subdir(dir);
         }
+        if let Some(template) = matches_init.value_of("template") {
+            generator = generator.template(template);
+        }
+        generate(
+            generator,
+            matches_init,
+            decoded,
+            author,
+            year,
+            &current_date,
+            home,
+        )?;
+    } else if let Some((vc, matches_init)) = ["hg", "darcs", "pijul"]
+        .iter()
+        .find_map(|vc| matches.subcommand_matches(vc).map(|m| (*vc, m)))
+    {
+        let repo = matches_init
+            .value_of("repo")
+            .expect("Clap failed to supply repository name");
+        let name = matches_init
+            .value_of("name")
+            .expect("Clap failed to supply project name");
 
-        let hash_with_files = HashBuilder::new().insert("files", files);
-
-        // render appropriate stuff by name.
-        match template_str {
-            "plain" => (),
-
-            "rust" => {
-                let mut bench_path = "benches/".to_string();
-                bench_path.push_str(name);
-                bench_path.push_str(".rs");
-                write_file_plain(includes::RUST_LIB, name, "src/lib.rs");
-                write_file_plain(includes::RUST_MAIN, name, "src/main.rs");
-                write_file_plain(includes::RUST_TRAVIS_CI, name, ".travis.yml");
-                write_file_plain(includes::RUST_GITIGNORE, name, ".gitignore");
-                write_file_plain(includes::RUST_BENCHMARKS, name, &bench_path);
-                render_file(includes::CARGO_TOML, name, "Cargo.toml", &hash)
-            }
-
-            "vim" | "vimscript" => {
-                write_file_plain(includes::VIM_GITIGNORE, name, ".gitignore");
-                render_file(includes::VIM_TRAVIS, name, ".travis.yml", &hash_with_files);
-                render_file(includes::VIMBALL, name, "vimball.txt", &hash_with_files)
-            }
-
-            "python" => {
-                render_file(includes::PY_SETUP, name, "setup.py", &hash);
-                write_file_plain(includes::PY_CFG, name, "setup.cfg");
-                write_file_plain(includes::PY_GITIGNORE, name, ".gitignore");
-                let mut bin_path = "bin/".to_string();
-                bin_path.push_str(name);
-                render_file(includes::PY_BIN, name, &bin_path, &hash);
-            }
-
-            "miso" => {
-                write_file_plain(includes::MISO_SETUP_HS, name, "Setup.hs");
-                write_file_plain(includes::MISO_MAIN, name, "app/Main.hs");
-                write_file_plain(includes::MISO_LIB, name, "src/Lib.hs");
-                let mut cabal_path = name.to_string();
-                cabal_path.push_str(".cabal");
-                render_file(includes::MISO_CABAL, name, &cabal_path, &hash);
-                write_file_plain(includes::MISO_GITIGNORE, name, ".gitignore");
-                render_file(includes::MISO_STACK, name, "stack.yaml", &hash);
-                write_file_plain(includes::HLINT_TEMPLATE, name, ".hlint.yaml");
-                write_file_plain(includes::SHAKE_STACK, name, "stack-shake.yaml");
-                write_file_plain(includes::MISO_TRAVIS, name, ".travis.yml");
-                render_file(includes::MISO_SHAKE, name, "shake.hs", &hash);
-                render_file(includes::MISO_HTML, name, "web-src/index.html", &hash);
-                write_file_plain(includes::HASKELL_TRAVIS_CI, name, ".travis.yml");
-                write_file_plain(includes::STYLISH_HASKELL, name, ".stylish-haskell.yaml");
-                let mut shake_path = name.to_string();
-                shake_path.push_str("/shake.hs");
-                mk_executable(shake_path);
-            }
-
-            "madlang" | "mad" => {
-                let mut src_path = "src/".to_string();
-                src_path.push_str(name);
-                src_path.push_str(".mad");
-                render_file(includes::MADLANG_SRC, name, &src_path, &hash);
-            }
-
-            "idris" => {
-                let mut pkg_path = name.to_string();
-                pkg_path.push_str(".ipkg");
-                write_file_plain(includes::IDRIS_GITIGNORE, name, ".gitignore");
-                write_file_plain(includes::IDRIS_CTAGS, name, ".ctags");
-                let mut main_path = name.to_capitalized();
-                main_path.push_str(".idr");
-                render_file(includes::IPKG, name, &pkg_path, &hash);
-                render_file(includes::IPKG_TEST, name, "test.ipkg", &hash);
-                // render_file(includes::IDRIS_EXE, name, &main_path, &hash);
-                render_file(includes::IDRIS_TEST, name, "src/Test/Spec.idr", &hash);
-                let mut lib_path = "src/".to_string();
-                lib_path.push_str(&name.to_capitalized());
-                lib_path.push('/');
-                lib_path.push_str("Lib.idr");
-                render_file(includes::IDRIS_LIB, name, &lib_path, &hash);
-            }
-
-            "julia" => {
-                write_file_plain(includes::JULIA_REQUIRE, name, "REQUIRE");
-                let mut project_path = "src/".to_string();
-                project_path.push_str(name.to_capitalized().as_str());
-                project_path.push_str(".jl");
-                write_file_plain(includes::JULIA_GITIGNORE, name, ".gitignore");
-                write_file_plain(includes::JULIA_SRC, name, &project_path);
-                write_file_plain(includes::JULIA_TEST, name, "test/test.jl");
-            }
-
-            "ats" => {
-                write_file_plain(includes::ATS_CTAGS, name, ".ctags");
-                let mut src_path = "src/".to_string();
-                src_path.push_str(name);
-                src_path.push_str(".dats");
-                render_file(includes::ATS_SRC, name, &src_path, &hash);
-                write_file_plain(includes::ATS_FORMAT, name, ".atsfmt.toml");
-                write_file_plain(includes::ATS_TRAVIS, name, ".clang-format");
-                render_file(includes::ATS_PKG, name, "atspkg.dhall", &hash);
-                render_file(includes::ATS_LIB, name, "pkg.dhall", &hash);
-                render_file(includes::ATS_TRAVIS, name, ".travis.yml", &hash);
-                render_file(includes::ATS_GITIGNORE, name, ".gitignore", &hash);
-            }
-
-            "haskell" | "kmett" => {
-                write_file_plain(includes::SETUP_HS, name, "Setup.hs");
-                write_file_plain(includes::MAIN, name, "app/Main.hs");
-                render_file(includes::LIB, name, "src/Lib.hs", &hash);
-                write_file_plain(includes::BENCH, name, "bench/Bench.hs");
-                write_file_plain(includes::TEST, name, "test/Spec.hs");
-                write_file_plain(includes::HLINT_TEMPLATE, name, ".hlint.yaml");
-                write_file_plain(includes::STYLISH_HASKELL, name, ".stylish-haskell.yaml");
-                render_file(includes::DEFAULT_NIX, name, "default.nix", &hash);
-                render_file(includes::RELEASE_NIX, name, "release.nix", &hash);
-                let mut cabal_path = name.to_string();
-                cabal_path.push_str(".cabal");
-                if template_str == "haskell" {
-                    render_file(includes::CABAL, name, &cabal_path, &hash);
-                } else {
-                    render_file(includes::KMETT, name, &cabal_path, &hash);
-                }
-                write_file_plain(includes::HASKELL_GITIGNORE, name, ".gitignore");
-                write_file_plain(includes::RELEASE_NIX, name, "release.nix");
-                write_file_plain(includes::HSPEC, name, ".hspec");
-                write_file_plain(includes::HS_GITATTRIBUTES, name, ".gitattributes");
-                render_file(includes::STACK_YAML, name, "stack.yaml", &hash);
-                render_file(includes::CABAL_PROJECT, name, "cabal.project.local", &hash);
-                render_file(includes::HASKELL_TRAVIS_CI, name, ".travis.yml", &hash);
-                render_file(includes::HASKELL_APPVEYOR, name, "appveyor.yml", &hash);
-                render_file(includes::HS_CHANGELOG, name, "CHANGELOG.md", &hash);
-            }
-
-            _ => std::process::exit(0x0f01),
+        // the repository is given as REPO[//SUBDIR][@REVISION], as for git
+        let (repo, revision) = remote::split_revision(repo);
+        let (repo, subdir) = remote::split_subdir(repo);
+        let url = remote::absolute(repo);
+        let source = match vc {
+            "hg" => Source::Hg(url),
+            "darcs" => Source::Darcs(url),
+            _ => Source::Pijul(url),
         };
 
-        // initialize version control
-        if let Some(vc) = decoded.version_control {
-            match vc.as_str() {
-                "git" => repo::git_init(name),
-                "hg" | "mercurial" => repo::hg_init(name),
-                "pijul" => repo::pijul_init(name),
-                "darcs" => repo::darcs_init(name),
-                _ => {
-                    eprintln!(
-                        "{}: version control {} is not yet supported. Supported version control tools are darcs, pijul, mercurial, and git.",
-                        "Error".red(),
-                        vc
-                    );
-                }
-            }
+        let mut generator = Generator::new(source, name);
+        if let Some(rev) = revision {
+            generator = generator.revision(rev);
+        }
+        if let Some(dir) = matches_init.value_of("subdir").or(subdir) {
+            generator = generator.subdir(dir);
         }
+        if let Some(template) = matches_init.value_of("template") {
+            generator = generator.template(template);
+        }
+        generate(
+            generator,
+            matches_init,
+            decoded,
+            author,
+            year,
+            &current_date,
+            home,
+        )?;
+    } else if let Some(matches_init) = matches.subcommand_matches("new") {
+        // get project directory
+        let dir = matches_init
+            .value_of("name")
+            .expect("Clap failed to supply project name");
 
-        // Print that we're done
-        println!("Finished initializing project in {}/", name);
-    } else if let Some(matches_init) = matches.subcommand_matches("init") {
-        let force: bool = matches_init.occurrences_of("force") == 1;
+        // get project template type
+        let template_str = matches_init
+            .value_of("template")
+            .expect("Clap failed to supply project directory");
 
+        let generator = Generator::new(Source::Builtin(template_str.to_string()), dir);
+        generate(
+            generator,
+            matches_init,
+            decoded,
+            author,
+            year,
+            &current_date,
+            home,
+        )?;
+    } else if let Some(matches_init) = matches.subcommand_matches("init") {
         // get project name
         let name = matches_init
             .value_of("name")
@@ -511,22 +630,23 @@ fn main() {
             .value_of("directory")
             .expect("Failed to supply project directory");
 
-        // read template.toml for template
-        let mut template_path = project_dir.to_string();
-        template_path.push_str("/template.toml");
-        let (parsed_toml, is_global_project) = read_toml_dir(&template_path, home.clone());
-
-        init_helper(
-            home,
-            project_dir,
+        // an archive, local or on a web server, or a directory
+        let source =
+            if remote::is_http(project_dir) || archive::Format::from_path(project_dir).is_some() {
+                Source::Archive(project_dir.to_string())
+            } else {
+                Source::Dir(project_dir.to_string())
+            };
+        let generator = Generator::new(source, name);
+        generate(
+            generator,
+            matches_init,
             decoded,
             author,
-            name,
             year,
             &current_date,
-            force,
-            parsed_toml,
-            is_global_project,
-        )
+            home,
+        )?;
     }
+    Ok(())
 }
@@ -0,0 +1,403 @@
+//! Module for the manifest of what a generation created, which makes it
+//! possible to undo it.
+extern crate sha2;
+
+use self::sha2::{Digest, Sha256};
+use colored::*;
+use error::PiError;
+use std::fs;
+use std::path::{Component, Path};
+use toml;
+
+/// Name of the manifest written into generated projects.
+pub const FILE: &str = ".pi-manifest.toml";
+
+/// A file that was written, and a hash of what was written.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileHash {
+    pub path: String,
+    pub sha256: String,
+}
+
+/// Everything a generation created, with paths relative to the project.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Whether the project directory itself was created.
+    #[serde(default)]
+    pub created_directory: bool,
+    #[serde(default)]
+    pub directories: Vec<String>,
+    #[serde(default, rename = "file")]
+    pub files: Vec<FileHash>,
+}
+
+/// What `undo` did.
+#[derive(Debug, Clone, Default)]
+pub struct Undo {
+    /// Files and directories that were removed.
+    pub removed: Vec<String>,
+    /// Files that were changed since they were generated, and so were kept.
+    pub modified: Vec<String>,
+    /// Directories that were kept, as there's something else in them now.
+    pub kept: Vec<String>,
+}
+
+impl Undo {
+    /// Print what was kept, if anything.
+    pub fn summary(&self) {
+        for m in &self.modified {
+            println!(
+                "{}: {} was changed since it was generated",
+                "Kept".yellow(),
+                m
+            );
+        }
+        for k in &self.kept {
+            println!("{}: {} is not empty", "Kept".yellow(), k);
+        }
+    }
+}
+
+/// SHA-256 of `contents`, in hex.
+pub fn sha256(contents: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(contents))
+}
+
+impl Manifest {
+    /// Read the manifest at `path`.
+    pub fn read(path: &str) -> Result<Manifest, PiError> {
+        let contents =
+            fs::read_to_string(path).map_err(|_| PiError::MissingTemplate(path.to_string()))?;
+        toml::from_str(contents.trim_start_matches('\u{feff}'))
+            .map_err(|e| PiError::Parse(path.to_string(), e))
+    }
+
+    /// Add what an earlier generation into the same directory created, as
+    /// recorded in `earlier`. Files written again keep their new hashes.
+    pub fn merge(&mut self, earlier: Manifest) {
+        self.created_directory |= earlier.created_directory;
+        for f in earlier.files {
+            if !self.files.iter().any(|g| g.path == f.path) {
+                self.files.push(f);
+            }
+        }
+        for d in earlier.directories {
+            if !self.directories.contains(&d) {
+                self.directories.push(d);
+            }
+        }
+    }
+
+    /// The manifest as the contents of a manifest file.
+    pub fn to_toml(&self) -> String {
+        let mut s =
+            "# Generated by pi. Run `pi undo` in this directory to remove what it created.\n"
+                .to_string();
+        s.push_str(&toml::to_string(self).unwrap_or_default());
+        s
+    }
+}
+
+/// Bring the manifest of the project in `dir` up to date after pi changed
+/// it: `written` files now hold what pi wrote, `created` directories are new,
+/// and `removed` files and directories are gone. Paths are relative to the
+/// project. Projects without a manifest are left alone.
+pub fn record(
+    dir: &Path,
+    written: &[String],
+    created: &[String],
+    removed: &[String],
+) -> Result<(), PiError> {
+    let manifest_path = dir.join(FILE);
+    let shown = manifest_path.to_string_lossy().to_string();
+    if !manifest_path.exists() {
+        return Ok(());
+    }
+    let mut manifest = Manifest::read(&shown)?;
+    manifest.files.retain(|f| !removed.contains(&f.path));
+    manifest.directories.retain(|d| !removed.contains(d));
+    for path in written {
+        let hash = sha256(&fs::read(dir.join(path))?);
+        match manifest.files.iter_mut().find(|f| f.path == *path) {
+            Some(f) => f.sha256 = hash,
+            None => manifest.files.push(FileHash {
+                path: path.clone(),
+                sha256: hash,
+            }),
+        }
+    }
+    for d in created {
+        if !manifest.directories.contains(d) {
+            manifest.directories.push(d.clone());
+        }
+    }
+    fs::write(&manifest_path, manifest.to_toml())?;
+    Ok(())
+}
+
+/// Remove the files and directories created when the project in `dir` was
+/// generated, as recorded in its manifest. Files that were changed since are
+/// never removed: unless `keep_modified` is set, nothing is removed if there
+/// are any.
+pub fn undo(dir: &str, keep_modified: bool) -> Result<Undo, PiError> {
+    let project = Path::new(dir);
+    let manifest_path = project.join(FILE);
+    let shown_manifest = manifest_path.to_string_lossy().to_string();
+    let manifest = Manifest::read(&shown_manifest)?;
+    // the manifest is just a file in the project, which anyone could edit
+    let paths = manifest.files.iter().map(|f| &f.path);
+    if let Some(p) = paths
+        .chain(manifest.directories.iter())
+        .find(|p| !is_inside(p))
+    {
+        return Err(PiError::OutsideProject(shown_manifest, p.to_string()));
+    }
+
+    // work out what's been changed before touching anything
+    let mut undo = Undo::default();
+    let mut unchanged = Vec::new();
+    let mut remaining = Manifest::default();
+    for f in &manifest.files {
+        let target = project.join(&f.path);
+        let shown = target.to_string_lossy().to_string();
+        match fs::read(&target) {
+            Ok(ref contents) if sha256(contents) == f.sha256 => unchanged.push(target),
+            Ok(_) => {
+                undo.modified.push(shown);
+                remaining.files.push(f.clone());
+            }
+            // already gone
+            Err(_) => (),
+        }
+    }
+    if !undo.modified.is_empty() && !keep_modified {
+        return Err(PiError::Modified(undo.modified));
+    }
+
+    for target in unchanged {
+        fs::remove_file(&target)?;
+        undo.removed.push(target.to_string_lossy().to_string());
+    }
+    // deepest first, so directories are empty by the time we get to them
+    let mut directories = manifest.directories.clone();
+    directories.sort();
+    for d in directories.iter().rev() {
+        let target = project.join(d);
+        let shown = target.to_string_lossy().to_string();
+        if !target.is_dir() {
+            continue;
+        }
+        if fs::remove_dir(&target).is_ok() {
+            undo.removed.push(shown);
+        } else {
+            undo.kept.push(shown);
+            remaining.directories.push(d.to_string());
+        }
+    }
+
+    // keep track of whatever is left, in case it's to be undone later
+    if remaining.files.is_empty() && remaining.directories.is_empty() {
+        fs::remove_file(&manifest_path)?;
+        if manifest.created_directory {
+            if fs::remove_dir(project).is_ok() {
+                undo.removed.push(dir.to_string());
+            } else {
+                undo.kept.push(dir.to_string());
+            }
+        }
+    } else {
+        remaining.created_directory = manifest.created_directory;
+        fs::write(&manifest_path, remaining.to_toml())?;
+    }
+    Ok(undo)
+}
+
+// Whether the relative `path` stays within the directory it's relative to.
+fn is_inside(path: &str) -> bool {
+    let path = Path::new(path);
+    path.components().any(|c| matches!(c, Component::Normal(_)))
+        && path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_) | Component::CurDir))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    // A generated project in `dir`: `proj/README.md`, `proj/src/lib.rs`, and
+    // `proj/src/util`, with a manifest listing them.
+    fn project(dir: &Path) -> String {
+        let project = dir.join("proj");
+        fs::create_dir_all(project.join("src").join("util")).unwrap();
+        let mut files = Vec::new();
+        for (path, contents) in &[("README.md", "# proj\n"), ("src/lib.rs", "")] {
+            fs::write(project.join(path), contents).unwrap();
+            files.push(FileHash {
+                path: path.to_string(),
+                sha256: sha256(contents.as_bytes()),
+            });
+        }
+        let manifest = Manifest {
+            created_directory: true,
+            directories: vec!["src".to_string(), "src/util".to_string()],
+            files,
+        };
+        fs::write(project.join(FILE), manifest.to_toml()).unwrap();
+        project.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn undo_removes_what_was_created() {
+        let dir = TempDir::new("pi-manifest").unwrap();
+        let project = project(dir.path());
+        let undo = undo(&project, false).unwrap();
+        assert!(undo.modified.is_empty());
+        assert!(undo.kept.is_empty());
+        assert_eq!(undo.removed.len(), 5);
+        assert!(!Path::new(&project).exists());
+        assert!(dir.path().exists());
+    }
+
+    #[test]
+    fn undo_keeps_everything_else() {
+        let dir = TempDir::new("pi-manifest").unwrap();
+        let project = project(dir.path());
+        let root = Path::new(&project);
+        fs::write(root.join("notes.txt"), "mine").unwrap();
+        fs::write(root.join("src").join("extra.rs"), "mine").unwrap();
+        let undo = undo(&project, false).unwrap();
+        assert!(!root.join("README.md").exists());
+        assert!(!root.join("src").join("lib.rs").exists());
+        assert!(!root.join("src").join("util").exists());
+        assert!(root.join("notes.txt").exists());
+        assert!(root.join("src").join("extra.rs").exists());
+        assert_eq!(
+            undo.kept,
+            vec![root.join("src").to_string_lossy().to_string()]
+        );
+        // what's left is still recorded
+        let left = Manifest::read(&root.join(FILE).to_string_lossy()).unwrap();
+        assert_eq!(left.directories, vec!["src".to_string()]);
+        assert!(left.files.is_empty());
+    }
+
+    #[test]
+    fn undo_refuses_when_files_were_changed() {
+        let dir = TempDir::new("pi-manifest").unwrap();
+        let project = project(dir.path());
+        let readme = Path::new(&project).join("README.md");
+        fs::write(&readme, "# changed\n").unwrap();
+        match undo(&project, false) {
+            Err(PiError::Modified(m)) => {
+                assert_eq!(m, vec![readme.to_string_lossy().to_string()])
+            }
+            other => panic!("expected Modified, got {:?}", other.map(|u| u.removed)),
+        }
+        // nothing was touched
+        assert!(Path::new(&project).join("src").join("lib.rs").exists());
+        assert!(Path::new(&project).join(FILE).exists());
+    }
+
+    #[test]
+    fn undo_keeps_changed_files() {
+        let dir = TempDir::new("pi-manifest").unwrap();
+        let project = project(dir.path());
+        let root = Path::new(&project);
+        fs::write(root.join("src").join("lib.rs"), "changed").unwrap();
+        let undo = undo(&project, true).unwrap();
+        assert_eq!(
+            undo.modified,
+            vec![root
+                .join("src")
+                .join("lib.rs")
+                .to_string_lossy()
+                .to_string()]
+        );
+        assert_eq!(
+            fs::read_to_string(root.join("src").join("lib.rs")).unwrap(),
+            "changed"
+        );
+        assert!(!root.join("README.md").exists());
+        assert!(!root.join("src").join("util").exists());
+        let left = Manifest::read(&root.join(FILE).to_string_lossy()).unwrap();
+        assert_eq!(left.files.len(), 1);
+        assert_eq!(left.files[0].path, "src/lib.rs");
+        assert!(left.created_directory);
+    }
+
+    #[test]
+    fn merge_keeps_earlier_entries() {
+        let file = |path: &str, hash: &str| FileHash {
+            path: path.to_string(),
+            sha256: hash.to_string(),
+        };
+        let mut later = Manifest {
+            created_directory: false,
+            directories: vec!["src".to_string()],
+            files: vec![file("README.md", "new")],
+        };
+        later.merge(Manifest {
+            created_directory: true,
+            directories: vec!["src".to_string(), "test".to_string()],
+            files: vec![file("README.md", "old"), file("LICENSE", "old")],
+        });
+        assert!(later.created_directory);
+        assert_eq!(
+            later.directories,
+            vec!["src".to_string(), "test".to_string()]
+        );
+        assert_eq!(
+            later.files,
+            vec![file("README.md", "new"), file("LICENSE", "old")]
+        );
+    }
+
+    #[test]
+    fn paths_inside_the_project() {
+        assert!(is_inside("src/main.rs"));
+        assert!(is_inside("./README.md"));
+        assert!(!is_inside("../elsewhere"));
+        assert!(!is_inside("src/../../elsewhere"));
+        assert!(!is_inside("/etc/passwd"));
+        assert!(!is_inside(""));
+        assert!(!is_inside("."));
+    }
+
+    #[test]
+    fn undo_refuses_paths_outside_the_project() {
+        let dir = TempDir::new("pi-manifest").unwrap();
+        let project = dir.path().join("proj");
+        fs::create_dir(&project).unwrap();
+        let outside = dir.path().join("outside");
+        fs::write(&outside, "keep me").unwrap();
+        for path in &[
+            "../outside".to_string(),
+            outside.to_string_lossy().to_string(),
+        ] {
+            let manifest = Manifest {
+                created_directory: true,
+                directories: Vec::new(),
+                files: vec![FileHash {
+                    path: path.clone(),
+                    sha256: sha256(b"keep me"),
+                }],
+            };
+            fs::write(project.join(FILE), manifest.to_toml()).unwrap();
+            assert!(matches!(
+                undo(&project.to_string_lossy(), true),
+                Err(PiError::OutsideProject(_, _))
+            ));
+            assert!(outside.exists());
+        }
+        let manifest = Manifest {
+            created_directory: true,
+            directories: vec!["..".to_string()],
+            files: Vec::new(),
+        };
+        fs::write(project.join(FILE), manifest.to_toml()).unwrap();
+        assert!(undo(&project.to_string_lossy(), true).is_err());
+        assert!(project.join(FILE).exists());
+    }
+}
@@ -0,0 +1,197 @@
+//! Module for registry indexes: TOML files listing templates by name, along
+//! with where to get them, for discovering templates.
+use colored::*;
+use error::PiError;
+use includes;
+use remote;
+use std::fs;
+use toml;
+use types::Config;
+
+/// Name given to the index built into pi.
+pub const BUILTIN: &str = "builtin";
+
+/// A template listed in an index.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct Entry {
+    pub name: String,
+    /// Where to get the template: a git repository, as given to `pi git`, or
+    /// anything `pi template install` takes.
+    pub source: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Entry {
+    /// Whether `term` appears in the template's name, source, description,
+    /// or tags, ignoring case.
+    pub fn matches(&self, term: &str) -> bool {
+        let term = term.to_lowercase();
+        let found = |s: &str| s.to_lowercase().contains(&term);
+        found(&self.name)
+            || found(&self.source)
+            || self.description.as_deref().map(found).unwrap_or(false)
+            || self.tags.iter().any(|t| found(t))
+    }
+}
+
+#[derive(Deserialize)]
+struct Index {
+    #[serde(default, rename = "template")]
+    templates: Vec<Entry>,
+}
+
+/// An index and the templates it lists.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Registry {
+    /// Path or URL of the index, or `BUILTIN`.
+    pub location: String,
+    pub templates: Vec<Entry>,
+}
+
+/// Parse the index at `location` from its `contents`.
+pub fn parse(contents: &str, location: &str) -> Result<Registry, PiError> {
+    let index: Index =
+        toml::from_str(contents).map_err(|e| PiError::Parse(location.to_string(), e))?;
+    Ok(Registry {
+        location: location.to_string(),
+        templates: index.templates,
+    })
+}
+
+/// Read the index at `location`, a path or an HTTP URL.
+pub fn read(location: &str) -> Result<Registry, PiError> {
+    let contents = if remote::is_http(location) {
+        let bytes = remote::download(location)?;
+        String::from_utf8_lossy(&bytes).to_string()
+    } else {
+        fs::read_to_string(remote::expand_home(location))
+            .map_err(|_| PiError::MissingTemplate(location.to_string()))?
+    };
+    parse(&contents, location)
+}
+
+/// The index built into pi, followed by those listed under `registries` in
+/// `config`. Indexes that can't be read are skipped with a warning.
+pub fn all(config: &Config) -> Vec<Registry> {
+    let mut registries = Vec::new();
+    match parse(includes::REGISTRY, BUILTIN) {
+        Ok(r) => registries.push(r),
+        Err(e) => eprintln!("{}: {}", "Warning".yellow(), e),
+    }
+    for location in config.registries.iter().flatten() {
+        match read(location) {
+            Ok(r) => registries.push(r),
+            Err(e) => eprintln!(
+                "{}: skipping the registry index {}: {}",
+                "Warning".yellow(),
+                location,
+                e
+            ),
+        }
+    }
+    registries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempdir::TempDir;
+
+    const INDEX: &str = r#"
+[[template]]
+name = "rust-service"
+source = "gh:org/templates//rust-service"
+description = "An HTTP service with Axum"
+tags = ["Rust", "web"]
+
+[[template]]
+name = "minimal"
+source = "https://example.com/minimal.tar.gz"
+"#;
+
+    fn config(registries: Vec<String>) -> Config {
+        Config {
+            version_control: None,
+            author: None,
+            license: None,
+            user: None,
+            git: None,
+            registries: Some(registries),
+        }
+    }
+
+    #[test]
+    fn parse_index() {
+        let registry = parse(INDEX, "index.toml").unwrap();
+        assert_eq!(registry.location, "index.toml");
+        assert_eq!(registry.templates.len(), 2);
+        assert_eq!(
+            registry.templates[0],
+            Entry {
+                name: "rust-service".to_string(),
+                source: "gh:org/templates//rust-service".to_string(),
+                description: Some("An HTTP service with Axum".to_string()),
+                tags: vec!["Rust".to_string(), "web".to_string()],
+            }
+        );
+        assert_eq!(registry.templates[1].description, None);
+        assert!(registry.templates[1].tags.is_empty());
+        // an empty index lists nothing
+        assert!(parse("", "empty.toml").unwrap().templates.is_empty());
+        assert!(matches!(
+            parse("[[template]]\nname = \"no source\"\n", "bad.toml"),
+            Err(PiError::Parse(_, _))
+        ));
+    }
+
+    #[test]
+    fn builtin_index_parses() {
+        let builtin = parse(includes::REGISTRY, BUILTIN).unwrap();
+        assert!(!builtin.templates.is_empty());
+    }
+
+    #[test]
+    fn entries_match() {
+        let registry = parse(INDEX, "index.toml").unwrap();
+        let service = &registry.templates[0];
+        assert!(service.matches("service"));
+        assert!(service.matches("org/templates"));
+        assert!(service.matches("axum"));
+        assert!(service.matches("rust"));
+        assert!(service.matches("WEB"));
+        assert!(!service.matches("haskell"));
+        let minimal = &registry.templates[1];
+        assert!(minimal.matches("tar.gz"));
+        assert!(!minimal.matches("axum"));
+    }
+
+    #[test]
+    fn all_skips_unreadable_indexes() {
+        let dir = TempDir::new("pi-registry").unwrap();
+        let good = dir.path().join("good.toml");
+        fs::write(&good, INDEX).unwrap();
+        let bad = dir.path().join("bad.toml");
+        fs::write(&bad, "[[template]\n").unwrap();
+        let missing = dir.path().join("missing.toml");
+        let locations: Vec<String> = [&missing, &bad, &good]
+            .iter()
+            .map(|p| p.to_string_lossy().to_string())
+            .collect();
+        let registries = all(&config(locations.clone()));
+        let found: Vec<&str> = registries.iter().map(|r| r.location.as_str()).collect();
+        assert_eq!(found, vec![BUILTIN, locations[2].as_str()]);
+        assert_eq!(registries[1].templates.len(), 2);
+    }
+
+    #[test]
+    fn all_without_registries() {
+        let mut config = config(Vec::new());
+        config.registries = None;
+        let registries = all(&config);
+        assert_eq!(registries.len(), 1);
+        assert_eq!(registries[0].location, BUILTIN);
+    }
+}
@@ -0,0 +1,516 @@
+//! Module for working out where a remote template lives, and fetching it.
+
+extern crate dirs;
+extern crate ureq;
+
+use error::PiError;
+use git2;
+use git2::build::CheckoutBuilder;
+use git2::{Cred, CredentialType, FetchOptions, RemoteCallbacks, Repository};
+use std::env;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+use types::GitConfig;
+
+// Shorthands for well-known forges, e.g. `gl:org/repo`.
+const FORGES: &[(&str, &str)] = &[
+    ("gh:", "https://github.com/"),
+    ("gl:", "https://gitlab.com/"),
+    ("bb:", "https://bitbucket.org/"),
+    ("cb:", "https://codeberg.org/"),
+];
+
+/// Turn a repository as given on the command line into something git can
+/// clone. Full URLs (`https://`, `ssh://`, `file://`, ...) and scp-style
+/// addresses (`git@host:org/repo`) are left as they are, and paths to local
+/// repositories are made absolute. `gh:`, `gl:`, `bb:`, and `cb:` expand to
+/// GitHub, GitLab, Bitbucket, and Codeberg, and a bare `user/repo` is taken
+/// to be on GitHub.
+pub fn expand(repo: &str) -> String {
+    for &(prefix, base) in FORGES {
+        if let Some(rest) = repo.strip_prefix(prefix) {
+            let mut url = base.to_string();
+            url.push_str(rest);
+            return url;
+        }
+    }
+    if repo.contains("://") || is_scp_like(repo) {
+        return repo.to_string();
+    }
+    if is_local(repo) {
+        // in full, so it still points to the same place from elsewhere
+        return match Path::new(repo).canonicalize() {
+            Ok(p) => p.to_string_lossy().to_string(),
+            Err(_) => repo.to_string(),
+        };
+    }
+    let mut url = "https://github.com/".to_string();
+    url.push_str(repo);
+    url
+}
+
+/// Make `repo` absolute if it's a local directory, for tools other than git,
+/// which have no shorthands for hosted repositories.
+pub fn absolute(repo: &str) -> String {
+    if repo.contains("://") || !Path::new(repo).is_dir() {
+        return repo.to_string();
+    }
+    match Path::new(repo).canonicalize() {
+        Ok(p) => p.to_string_lossy().to_string(),
+        Err(_) => repo.to_string(),
+    }
+}
+
+/// Split the revision off a repository given as `repo@revision` or
+/// `repo#branch`. The revision can be a tag, a branch, or a commit.
+pub fn split_revision(repo: &str) -> (&str, Option<&str>) {
+    if let Some(i) = repo.find('#') {
+        return (&repo[..i], Some(&repo[i + 1..]));
+    }
+    // an `@` in the last part of the path, so `git@host:repo` isn't split
+    let last = repo.rfind(['/', ':']).map(|i| i + 1).unwrap_or(0);
+    match repo[last..].rfind('@') {
+        Some(i) => (&repo[..last + i], Some(&repo[last + i + 1..])),
+        None => (repo, None),
+    }
+}
+
+/// Split off the directory within the repository that the template is in,
+/// given as `repo//subdir`.
+pub fn split_subdir(repo: &str) -> (&str, Option<&str>) {
+    // skip the `//` of `scheme://`
+    let start = repo.find("://").map(|i| i + 3).unwrap_or(0);
+    match repo[start..].find("//") {
+        Some(i) => (&repo[..start + i], Some(&repo[start + i + 2..])),
+        None => (repo, None),
+    }
+}
+
+/// Whether `location` is an HTTP or HTTPS URL.
+pub fn is_http(location: &str) -> bool {
+    let lower = location.to_lowercase();
+    lower.starts_with("http://") || lower.starts_with("https://")
+}
+
+/// `path` with a leading `~/` replaced by the home directory. `~/` is common
+/// in configuration files, but isn't expanded by anything else.
+pub fn expand_home(path: &str) -> PathBuf {
+    match (path.strip_prefix("~/"), dirs::home_dir()) {
+        (Some(rest), Some(home)) => home.join(rest),
+        _ => PathBuf::from(path),
+    }
+}
+
+/// Download the file at `url`.
+pub fn download(url: &str) -> Result<Vec<u8>, PiError> {
+    let error = |e: String| {
+        // ureq's messages start with the URL, which is part of ours already
+        let prefix = format!("{}: ", url);
+        let reason = e.strip_prefix(&prefix).unwrap_or(&e).to_string();
+        PiError::Download(url.to_string(), reason)
+    };
+    let response = ureq::get(url).call().map_err(|e| error(e.to_string()))?;
+    let mut contents = Vec::new();
+    response
+        .into_reader()
+        .read_to_end(&mut contents)
+        .map_err(|e| error(e.to_string()))?;
+    Ok(contents)
+}
+
+// `user@host:path`, as understood by ssh
+fn is_scp_like(repo: &str) -> bool {
+    match (repo.find('@'), repo.find(':')) {
+        (Some(at), Some(colon)) => at < colon && !repo[..colon].contains('/'),
+        _ => false,
+    }
+}
+
+fn is_local(repo: &str) -> bool {
+    repo.starts_with('/')
+        || repo.starts_with("./")
+        || repo.starts_with("../")
+        || is_repository(Path::new(repo))
+}
+
+// A repository with a work tree, or a bare one.
+fn is_repository(path: &Path) -> bool {
+    path.join(".git").exists() || path.join("HEAD").is_file()
+}
+
+/// Clone the repository at `url` into a temporary directory, checking out
+/// `revision` if given, and only `sparse` if given. The directory is removed
+/// once the returned `TempDir` is dropped.
+pub fn clone(
+    url: &str,
+    revision: Option<&str>,
+    sparse: Option<&str>,
+    git: &GitConfig,
+) -> Result<TempDir, PiError> {
+    let dir = TempDir::new("pi-template")?;
+    clone_into(url, dir.path(), revision, sparse, git)?;
+    Ok(dir)
+}
+
+/// Clone the repository at `url` into `path`, checking out `revision` if
+/// given. Only the commit that's checked out is fetched where possible, and
+/// with `sparse`, only that file or directory is checked out.
+pub fn clone_into(
+    url: &str,
+    path: &Path,
+    revision: Option<&str>,
+    sparse: Option<&str>,
+    git: &GitConfig,
+) -> Result<(), PiError> {
+    let clone_error = |e| PiError::Clone(url.to_string(), e);
+    let repo = Repository::init(path).map_err(clone_error)?;
+    repo.remote("origin", url).map_err(clone_error)?;
+    let rev = fetch(&repo, revision, git).map_err(clone_error)?;
+    checkout(&repo, &rev, sparse)
+        .map_err(|e| PiError::Revision(revision.unwrap_or("HEAD").to_string(), e))
+}
+
+/// Fetch `revision` from `origin` into `repo`, or the tip of its default
+/// branch if no revision is given. Returns what to check out.
+///
+/// Branches, tags, and commits at the tip of either are fetched without any
+/// history, as are other commits if the server allows it. Anything else
+/// needs the whole history.
+pub fn fetch(
+    repo: &Repository,
+    revision: Option<&str>,
+    git: &GitConfig,
+) -> Result<String, git2::Error> {
+    let mut remote = repo.find_remote("origin")?;
+    // libgit2 can't make shallow clones of local repositories
+    let shallow = remote
+        .url()
+        .map(|u| !u.starts_with("file://") && !is_local(u))
+        .unwrap_or(false);
+    let advertised: Vec<(String, git2::Oid, Option<String>)> = {
+        let connection = remote.connect_auth(git2::Direction::Fetch, Some(callbacks(git)), None)?;
+        connection
+            .list()?
+            .iter()
+            .map(|h| {
+                let target = h.symref_target().map(|t| t.to_string());
+                (h.name().to_string(), h.oid(), target)
+            })
+            .collect()
+    };
+
+    if let Some(name) = wanted_ref(&advertised, revision) {
+        let local = match name.strip_prefix("refs/heads/") {
+            Some(branch) => format!("refs/remotes/origin/{}", branch),
+            None => name.clone(),
+        };
+        let mut options = fetch_options(git);
+        if shallow {
+            options.depth(1);
+        }
+        remote.fetch(&[&format!("+{}:{}", name, local)], Some(&mut options), None)?;
+        return Ok(local);
+    }
+
+    let rev = revision.unwrap_or("HEAD");
+    // a commit on its own, which not every server allows
+    if shallow && rev.len() == 40 {
+        if let Ok(oid) = git2::Oid::from_str(rev) {
+            let mut options = fetch_options(git);
+            options.depth(1);
+            let fetched = remote.fetch(&[rev], Some(&mut options), None);
+            if fetched.is_ok() && repo.find_commit(oid).is_ok() {
+                return Ok(rev.to_string());
+            }
+        }
+    }
+    let mut options = fetch_options(git);
+    if repo.is_shallow() {
+        options.depth(i32::MAX);
+    }
+    remote.fetch(
+        &[
+            "+refs/heads/*:refs/remotes/origin/*",
+            "+refs/tags/*:refs/tags/*",
+        ],
+        Some(&mut options),
+        None,
+    )?;
+    Ok(rev.to_string())
+}
+
+// The branch or tag `revision` names among the refs a remote advertised, or
+// its default branch if no revision is given.
+fn wanted_ref(
+    advertised: &[(String, git2::Oid, Option<String>)],
+    revision: Option<&str>,
+) -> Option<String> {
+    let is_branch_or_tag = |name: &str| {
+        name.starts_with("refs/heads/")
+            || (name.starts_with("refs/tags/") && !name.ends_with("^{}"))
+    };
+    match revision {
+        None => {
+            let head = advertised.iter().find(|h| h.0 == "HEAD")?;
+            head.2.clone().or_else(|| {
+                advertised
+                    .iter()
+                    .find(|h| h.0.starts_with("refs/heads/") && h.1 == head.1)
+                    .map(|h| h.0.clone())
+            })
+        }
+        Some(rev) => {
+            let branch = format!("refs/heads/{}", rev);
+            let tag = format!("refs/tags/{}", rev);
+            let peeled = format!("{}^{{}}", tag);
+            advertised
+                .iter()
+                .find(|h| h.0 == branch || h.0 == tag || h.0 == peeled)
+                // a commit at the tip of a branch or tag
+                .or_else(|| {
+                    advertised
+                        .iter()
+                        .find(|h| is_branch_or_tag(&h.0) && h.1.to_string() == rev)
+                })
+                .map(|h| h.0.trim_end_matches("^{}").to_string())
+        }
+    }
+}
+
+fn fetch_options(git: &GitConfig) -> FetchOptions<'_> {
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(callbacks(git));
+    options
+}
+
+// Answer the remote's requests for credentials. Each kind is tried once, in
+// turn: the ssh-agent and then key files for SSH, and a token from
+// `PI_GIT_TOKEN` and then git's credential helpers for HTTPS.
+fn callbacks(git: &GitConfig) -> RemoteCallbacks<'_> {
+    let mut keys = ssh_keys(git);
+    let mut tried_agent = false;
+    let mut tried_token = false;
+    let mut tried_helper = false;
+    let mut tried_default = false;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username, allowed| {
+        let user = username.or(git.username.as_deref()).unwrap_or("git");
+        if allowed.contains(CredentialType::USERNAME) {
+            return Cred::username(user);
+        }
+        if allowed.contains(CredentialType::SSH_KEY) {
+            if !tried_agent {
+                tried_agent = true;
+                if let Ok(cred) = Cred::ssh_key_from_agent(user) {
+                    return Ok(cred);
+                }
+            }
+            if let Some(key) = keys.pop() {
+                return Cred::ssh_key(user, None, &key, None);
+            }
+        }
+        if allowed.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            if !tried_token {
+                tried_token = true;
+                if let Ok(token) = env::var("PI_GIT_TOKEN") {
+                    // forges take any user name along with a token
+                    let user = username
+                        .or(git.username.as_deref())
+                        .unwrap_or("x-access-token");
+                    return Cred::userpass_plaintext(user, &token);
+                }
+            }
+            if !tried_helper {
+                tried_helper = true;
+                if let Ok(config) = git2::Config::open_default() {
+                    if let Ok(cred) = Cred::credential_helper(&config, url, username) {
+                        return Ok(cred);
+                    }
+                }
+            }
+        }
+        if allowed.contains(CredentialType::DEFAULT) && !tried_default {
+            tried_default = true;
+            return Cred::default();
+        }
+        Err(git2::Error::from_str(
+            "no credentials were accepted. Add a key to ssh-agent, set ssh_key under [git] in ~/.pi.toml, set PI_GIT_TOKEN, or configure a git credential helper",
+        ))
+    });
+    callbacks
+}
+
+// SSH keys to try, last first: the one from the configuration, then the
+// usual ones in ~/.ssh.
+fn ssh_keys(git: &GitConfig) -> Vec<PathBuf> {
+    let home = dirs::home_dir().unwrap_or_else(|| PathBuf::from("."));
+    let mut keys: Vec<PathBuf> = ["id_rsa", "id_ecdsa", "id_ed25519"]
+        .iter()
+        .map(|k| home.join(".ssh").join(k))
+        .collect();
+    if let Some(ref key) = git.ssh_key {
+        keys.push(expand_home(key));
+    }
+    keys.retain(|k| k.is_file());
+    keys
+}
+
+/// Check out `rev`, which may be a branch of the remote, or anything
+/// `git rev-parse` understands, and only `sparse` if given. The remote's
+/// branches come first, so a local branch left behind by an earlier clone
+/// isn't used instead.
+pub fn checkout(repo: &Repository, rev: &str, sparse: Option<&str>) -> Result<(), git2::Error> {
+    let mut remote_branch = "origin/".to_string();
+    remote_branch.push_str(rev);
+    let object = repo
+        .revparse_single(&remote_branch)
+        .or_else(|_| repo.revparse_single(rev))?;
+    let mut builder = CheckoutBuilder::new();
+    builder.force();
+    if let Some(path) = sparse {
+        builder.path(path);
+    }
+    repo.checkout_tree(&object, Some(&mut builder))?;
+    repo.set_head_detached(object.peel_to_commit()?.id())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // how a repository given on the command line is taken apart
+    fn parse(source: &str) -> (String, Option<&str>, Option<&str>) {
+        let (repo, revision) = split_revision(source);
+        let (repo, subdir) = split_subdir(repo);
+        (expand(repo), subdir, revision)
+    }
+
+    #[test]
+    fn expand_shorthands() {
+        assert_eq!(expand("gh:org/repo"), "https://github.com/org/repo");
+        assert_eq!(expand("gl:org/repo"), "https://gitlab.com/org/repo");
+        assert_eq!(expand("bb:org/repo"), "https://bitbucket.org/org/repo");
+        assert_eq!(expand("cb:org/repo"), "https://codeberg.org/org/repo");
+        assert_eq!(expand("org/repo"), "https://github.com/org/repo");
+    }
+
+    #[test]
+    fn expand_home_only_at_the_start() {
+        if let Some(home) = dirs::home_dir() {
+            assert_eq!(expand_home("~/.ssh/key"), home.join(".ssh/key"));
+        }
+        assert_eq!(expand_home("/etc/key"), PathBuf::from("/etc/key"));
+        assert_eq!(expand_home("keys/~/key"), PathBuf::from("keys/~/key"));
+        assert_eq!(expand_home("~user/key"), PathBuf::from("~user/key"));
+    }
+
+    #[test]
+    fn expand_leaves_urls_alone() {
+        for url in &[
+            "https://example.com/org/repo.git",
+            "ssh://git@example.com/org/repo",
+            "file:///srv/repo.git",
+            "git@example.com:org/repo",
+        ] {
+            assert_eq!(expand(url), *url);
+        }
+    }
+
+    #[test]
+    fn expand_local_repositories() {
+        let dir = TempDir::new("pi-remote").unwrap();
+        let full = dir.path().canonicalize().unwrap();
+        let path = dir.path().to_string_lossy().to_string();
+        assert_eq!(expand(&path), full.to_string_lossy());
+    }
+
+    #[test]
+    fn expand_bare_repositories() {
+        // relative paths to these would otherwise be taken for `user/repo`
+        let dir = TempDir::new("pi-bare").unwrap();
+        assert!(!is_repository(dir.path()));
+        Repository::init_bare(dir.path()).unwrap();
+        assert!(is_repository(dir.path()));
+        let full = dir.path().canonicalize().unwrap();
+        assert_eq!(
+            expand(&dir.path().to_string_lossy()),
+            full.to_string_lossy()
+        );
+    }
+
+    #[test]
+    fn split_revisions() {
+        assert_eq!(split_revision("org/repo@v1"), ("org/repo", Some("v1")));
+        assert_eq!(split_revision("org/repo#main"), ("org/repo", Some("main")));
+        assert_eq!(split_revision("org/repo"), ("org/repo", None));
+        assert_eq!(
+            split_revision("git@host:org/repo@tag"),
+            ("git@host:org/repo", Some("tag"))
+        );
+        assert_eq!(split_revision("git@host:repo"), ("git@host:repo", None));
+        assert_eq!(
+            split_revision("https://user@host/repo"),
+            ("https://user@host/repo", None)
+        );
+    }
+
+    #[test]
+    fn split_subdirs() {
+        assert_eq!(split_subdir("org/repo//tpl/a"), ("org/repo", Some("tpl/a")));
+        assert_eq!(
+            split_subdir("file:///x.git//sub"),
+            ("file:///x.git", Some("sub"))
+        );
+        assert_eq!(
+            split_subdir("https://host/repo.git"),
+            ("https://host/repo.git", None)
+        );
+    }
+
+    #[test]
+    fn parse_repositories() {
+        assert_eq!(
+            parse("git@host:org/repo@tag"),
+            ("git@host:org/repo".to_string(), None, Some("tag"))
+        );
+        assert_eq!(
+            parse("file:///x.git//sub"),
+            ("file:///x.git".to_string(), Some("sub"), None)
+        );
+        assert_eq!(
+            parse("repo//sub@rev"),
+            (
+                "https://github.com/repo".to_string(),
+                Some("sub"),
+                Some("rev")
+            )
+        );
+        assert_eq!(
+            parse("gh:org/repo//tpl/a#dev"),
+            (
+                "https://github.com/org/repo".to_string(),
+                Some("tpl/a"),
+                Some("dev")
+            )
+        );
+    }
+
+    #[test]
+    fn scp_like() {
+        assert!(is_scp_like("git@host:org/repo"));
+        assert!(is_scp_like("git@host:repo"));
+        assert!(!is_scp_like("https://user@host/repo"));
+        assert!(!is_scp_like("./a@b:c"));
+        assert!(!is_scp_like("org/repo"));
+        assert!(!is_scp_like("host:repo"));
+    }
+
+    #[test]
+    fn http_urls() {
+        assert!(is_http("https://example.com/t.tar.gz"));
+        assert!(is_http("HTTP://example.com/t.zip"));
+        assert!(!is_http("file:///t.zip"));
+        assert!(!is_http("httpdocs/t.zip"));
+    }
+}
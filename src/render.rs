@@ -2,296 +2,1080 @@
 extern crate rustache;
 
 use self::rustache::*;
+use archive;
+use colored::*;
+use error::PiError;
+use filesystem::{Disk, Filesystem, Memory};
+use manifest;
+use manifest::{FileHash, Manifest};
+use similar::TextDiff;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fs;
 use std::fs::File;
+use std::io;
 use std::io::prelude::*;
 use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use tempdir::TempDir;
+
+/// What to do when a file we are about to write already exists.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Force {
+    /// Show the changes and ask before overwriting.
+    Ask,
+    /// Leave the existing file alone.
+    Skip,
+    /// Overwrite the existing file.
+    Overwrite,
+    /// Move the existing file to `<file>.bak`, then write.
+    Backup,
+}
+
+impl FromStr for Force {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Force, String> {
+        match s {
+            "ask" => Ok(Force::Ask),
+            "skip" => Ok(Force::Skip),
+            "overwrite" => Ok(Force::Overwrite),
+            "backup" => Ok(Force::Backup),
+            _ => Err(format!(
+                "unknown conflict policy '{}'; expected ask, skip, overwrite, or backup",
+                s
+            )),
+        }
+    }
+}
+
+/// Line endings to use in generated text files.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEndings {
+    Lf,
+    Crlf,
+    /// Whatever is usual on the platform pi is running on.
+    Native,
+}
+
+impl LineEndings {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEndings::Lf => "\n",
+            LineEndings::Crlf => "\r\n",
+            LineEndings::Native if cfg!(target_os = "windows") => "\r\n",
+            LineEndings::Native => "\n",
+        }
+    }
+}
+
+impl FromStr for LineEndings {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<LineEndings, String> {
+        match s {
+            "lf" => Ok(LineEndings::Lf),
+            "crlf" => Ok(LineEndings::Crlf),
+            "native" => Ok(LineEndings::Native),
+            _ => Err(format!(
+                "unknown line endings '{}'; expected lf, crlf, or native",
+                s
+            )),
+        }
+    }
+}
+
+/// How to normalize generated text files. Options that are `None` leave the
+/// rendered output as it is; files that aren't valid UTF-8 are never touched.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TextOptions {
+    pub line_endings: Option<LineEndings>,
+    /// Make sure files end with a line break (`true`) or don't (`false`).
+    pub trailing_newline: Option<bool>,
+    /// Add (`true`) or remove (`false`) a UTF-8 byte order mark.
+    pub bom: Option<bool>,
+}
+
+impl TextOptions {
+    /// Fill in any options not set here from `other`.
+    pub fn or(self, other: TextOptions) -> TextOptions {
+        TextOptions {
+            line_endings: self.line_endings.or(other.line_endings),
+            trailing_newline: self.trailing_newline.or(other.trailing_newline),
+            bom: self.bom.or(other.bom),
+        }
+    }
+
+    /// Apply the options to the contents of a file.
+    pub fn apply<'a>(&self, contents: &'a [u8]) -> Cow<'a, [u8]> {
+        if *self == TextOptions::default() || contents.is_empty() {
+            return Cow::Borrowed(contents);
+        }
+        let text = match std::str::from_utf8(contents) {
+            Ok(t) => t,
+            Err(_) => return Cow::Borrowed(contents),
+        };
+        let had_bom = text.starts_with('\u{feff}');
+        let mut body = text.trim_start_matches('\u{feff}').to_string();
+
+        if let Some(le) = self.line_endings {
+            body = body.replace("\r\n", "\n");
+            if le.as_str() != "\n" {
+                body = body.replace('\n', le.as_str());
+            }
+        }
+
+        match self.trailing_newline {
+            Some(true) if !body.is_empty() && !body.ends_with('\n') => {
+                let newline = match self.line_endings {
+                    Some(le) => le.as_str(),
+                    None if body.contains("\r\n") => "\r\n",
+                    None => "\n",
+                };
+                body.push_str(newline);
+            }
+            Some(false) => {
+                let len = body.trim_end_matches(['\n', '\r']).len();
+                body.truncate(len);
+            }
+            _ => (),
+        }
+
+        if self.bom.unwrap_or(had_bom) {
+            body.insert(0, '\u{feff}');
+        }
+        Cow::Owned(body.into_bytes())
+    }
+}
+
+/// What `Writer::write` did with a file.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Outcome {
+    /// The file was written.
+    Written,
+    /// The file was already there, with the same contents.
+    Unchanged,
+    /// A different file was already there, and was left alone.
+    Kept,
+}
+
+/// A file written by a `Writer`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Written {
+    /// Path as it appears once the project is in place.
+    pub path: String,
+    pub size: u64,
+    /// Unix permission bits.
+    pub mode: u32,
+    /// SHA-256 of the contents, in hex.
+    pub sha256: String,
+}
+
+/// Writes files into the project directory, deciding what to do with files
+/// that are already there.
+pub struct Writer {
+    pub force: Force,
+    pub diff: bool,
+    /// Print diffs and prompts to stderr, leaving stdout for a report.
+    pub stderr: bool,
+    pub text: TextOptions,
+    /// Only add files and directories that don't exist yet, leaving
+    /// everything already in the project directory alone.
+    pub merge: bool,
+    /// Files and directories written so far.
+    pub created: Vec<String>,
+    /// Details of the files written so far, in the order they were written.
+    pub files: Vec<Written>,
+    pub skipped: Vec<String>,
+    pub backups: Vec<String>,
+    fs: Box<dyn Filesystem>,
+    stage: Option<TempDir>,
+    target: String,
+    // backups of files already in the target, made on commit
+    renames: Vec<(String, String)>,
+    // pi's own files, which replace what's there even when merging
+    own: Vec<(String, String)>,
+    // the project directory as it's shown, and whether we're creating it
+    root: String,
+    new_root: bool,
+    // the project, when it's kept in memory
+    memory: Option<Memory>,
+    archive: Option<(archive::Format, String)>,
+}
+
+impl Writer {
+    /// Create a writer with the given conflict policy. When `diff` is set, a
+    /// unified diff is printed for every existing file that would change.
+    pub fn new(force: Force, diff: bool) -> Writer {
+        Writer::with_filesystem(force, diff, Box::new(Disk))
+    }
+
+    /// Create a writer that renders into `fs` rather than the real
+    /// filesystem.
+    pub fn with_filesystem(force: Force, diff: bool, fs: Box<dyn Filesystem>) -> Writer {
+        Writer {
+            force,
+            diff,
+            stderr: false,
+            text: TextOptions::default(),
+            merge: false,
+            created: Vec::new(),
+            files: Vec::new(),
+            skipped: Vec::new(),
+            backups: Vec::new(),
+            fs,
+            stage: None,
+            target: String::new(),
+            renames: Vec::new(),
+            own: Vec::new(),
+            root: String::new(),
+            new_root: false,
+            memory: None,
+            archive: None,
+        }
+    }
+
+    /// Keep the project in memory rather than writing it to disk. The
+    /// returned handle shows what has been rendered; `write_archive` turns it
+    /// into an archive.
+    pub fn in_memory(&mut self) -> Memory {
+        let memory = Memory::new();
+        self.fs = Box::new(memory.clone());
+        self.memory = Some(memory.clone());
+        memory
+    }
+
+    /// Write the project to an archive at `path` on `commit`, rather than to
+    /// disk. The format is picked from the extension: `.tar`, `.tar.gz`,
+    /// `.tgz`, or `.zip`.
+    pub fn archive(&mut self, path: &str) -> std::result::Result<(), PiError> {
+        let format = archive::Format::from_path(path)
+            .ok_or_else(|| PiError::UnknownArchive(path.to_string()))?;
+        self.in_memory();
+        self.archive = Some((format, path.to_string()));
+        Ok(())
+    }
+
+    /// Whether files are written to disk, as opposed to kept in memory.
+    pub fn on_disk(&self) -> bool {
+        self.fs.is_disk()
+    }
+
+    /// Write the project kept in memory to `sink` as an archive.
+    pub fn write_archive<W: Write + Seek>(
+        &self,
+        format: archive::Format,
+        sink: W,
+    ) -> io::Result<W> {
+        match self.memory {
+            Some(ref m) => archive::write(format, &m.entries(), sink),
+            None => archive::write(format, &BTreeMap::new(), sink),
+        }
+    }
+
+    /// Create a temporary directory next to `name` to render the project
+    /// into. Only what is written goes there; files already at `name` are
+    /// read from where they are. Returns the directory to render into;
+    /// nothing changes at `name` until `commit` is called, and the temporary
+    /// directory is removed if the writer is dropped first.
+    pub fn stage(&mut self, name: &str) -> io::Result<String> {
+        if !self.on_disk() {
+            // everything goes under a single directory named after the project
+            self.target = name.to_string();
+            self.root = super::project_name(name);
+            self.new_root = true;
+            if !self.fs.exists(&self.root) {
+                self.fs.create_dir(&self.root)?;
+            }
+            return Ok(self.root.clone());
+        }
+        let target = Path::new(name);
+        // resolve `.` and friends, so the temporary directory really is a sibling
+        let resolved = target
+            .canonicalize()
+            .unwrap_or_else(|_| target.to_path_buf());
+        let mut prefix = ".".to_string();
+        prefix.push_str(&resolved.file_name().unwrap_or_default().to_string_lossy());
+        prefix.push_str(".pi");
+        let stage = TempDir::new_in(parent_dir(&resolved), &prefix)?;
+        let root = stage.path().to_string_lossy().to_string();
+        self.stage = Some(stage);
+        self.target = name.to_string();
+        self.root = name.to_string();
+        self.new_root = !target.exists();
+        Ok(root)
+    }
+
+    /// Move the staged project into place, or write out the archive. A new
+    /// project is moved as a whole; into an existing directory, files are
+    /// moved one at a time, replacing what was there.
+    pub fn commit(&mut self) -> io::Result<()> {
+        if let Some((format, path)) = self.archive.clone() {
+            let result = File::create(&path).and_then(|f| self.write_archive(format, f));
+            if result.is_err() {
+                let _ = fs::remove_file(&path);
+            }
+            return result.map(|_| ());
+        }
+        let stage = match self.stage.take() {
+            Some(s) => s,
+            None => return Ok(()),
+        };
+        let target = Path::new(&self.target);
+        if self.merge && target.is_dir() {
+            // move over pi's own files and whatever is new, undoing it all if
+            // a move fails
+            let resolved = target.canonicalize()?;
+            let old = TempDir::new_in(parent_dir(&resolved), ".pi-old")?;
+            let mut moved = Vec::new();
+            let mut created = Vec::new();
+            let mut result = Ok(());
+            for (path, shown) in &self.own {
+                result = replace_file(Path::new(path), shown, old.path(), &mut moved);
+                if result.is_err() {
+                    break;
+                }
+            }
+            if result.is_ok() {
+                result = merge_dir(stage.path(), target, &mut created);
+            }
+            if let Err(e) = result {
+                for p in created.iter().rev() {
+                    let _ = if p.is_dir() {
+                        fs::remove_dir_all(p)
+                    } else {
+                        fs::remove_file(p)
+                    };
+                }
+                restore(&moved);
+                return Err(e);
+            }
+        } else if target.exists() {
+            // keep replaced files around until everything is in place, so it
+            // can all be put back if a move fails
+            let resolved = target.canonicalize()?;
+            let old = TempDir::new_in(parent_dir(&resolved), ".pi-old")?;
+            let mut renamed = Vec::new();
+            let mut moved = Vec::new();
+            let mut result = Ok(());
+            for (from, to) in &self.renames {
+                result = fs::rename(from, to);
+                if result.is_err() {
+                    break;
+                }
+                renamed.push((from, to));
+            }
+            if result.is_ok() {
+                result = replace_dir(stage.path(), target, old.path(), &mut moved);
+            }
+            if let Err(e) = result {
+                restore(&moved);
+                for (from, to) in renamed.iter().rev() {
+                    let _ = fs::rename(to, from);
+                }
+                return Err(e);
+            }
+        } else {
+            fs::rename(stage.path(), target)?;
+            let _ = stage.into_path();
+        }
+        Ok(())
+    }
+
+    /// Throw away the staged project, leaving the target as it was.
+    pub fn abort(&mut self) {
+        self.stage = None;
+    }
+
+    // Path as it will appear once the project has been moved into place.
+    fn display(&self, path: &str) -> String {
+        if let Some(ref stage) = self.stage {
+            if let Ok(rest) = Path::new(path).strip_prefix(stage.path()) {
+                return Path::new(&self.target)
+                    .join(rest)
+                    .to_string_lossy()
+                    .to_string();
+            }
+        }
+        path.to_string()
+    }
+
+    /// Write `contents` to `path`, unless the conflict policy says to leave
+    /// what's there alone.
+    pub fn write(&mut self, path: &str, contents: &[u8]) -> io::Result<Outcome> {
+        let text = self.text;
+        let contents: &[u8] = &text.apply(contents);
+        if self.merge {
+            let shown = self.display(path);
+            if self.fs.exists(&shown) {
+                self.skipped.push(shown);
+                return Ok(Outcome::Kept);
+            }
+        }
+        if let Some(existing) = self.existing(path) {
+            let old = self.fs.read_file(&existing)?;
+            if old == contents {
+                // nothing is staged, so there's nothing more to do with it
+                return Ok(Outcome::Unchanged);
+            }
+            let shown = self.display(path);
+            if self.diff || self.force == Force::Ask {
+                print_diff(&shown, &old, contents, self.stderr);
+            }
+            let keep = match self.force {
+                Force::Skip => true,
+                Force::Ask => !confirm(&shown, self.stderr),
+                Force::Overwrite => false,
+                Force::Backup => {
+                    let backup = self.backup_name(path);
+                    let shown_backup = self.display(&backup);
+                    if existing == path {
+                        self.fs.rename(path, &backup)?;
+                    } else {
+                        // the file is in the target, which is left alone until commit
+                        self.renames.push((existing, shown_backup.clone()));
+                    }
+                    self.backups.push(shown_backup);
+                    false
+                }
+            };
+            if keep {
+                self.skipped.push(shown);
+                return Ok(Outcome::Kept);
+            }
+        }
+        self.fs.write_file(path, contents)?;
+        let shown = self.display(path);
+        // a file written twice only shows up once, with what it ended up as
+        self.files.retain(|f| f.path != shown);
+        self.files.push(Written {
+            path: shown.clone(),
+            size: contents.len() as u64,
+            mode: self.fs.mode(path).unwrap_or(0o644),
+            sha256: manifest::sha256(contents),
+        });
+        self.created.push(shown);
+        Ok(Outcome::Written)
+    }
+
+    /// Write one of pi's own files, such as the manifest, to `path`. Unlike
+    /// `write`, this replaces whatever is there, whatever the conflict
+    /// policy, and leaves the contents as they are.
+    pub fn write_own(&mut self, path: &str, contents: &[u8]) -> io::Result<()> {
+        self.fs.write_file(path, contents)?;
+        let shown = self.display(path);
+        self.files.retain(|f| f.path != shown);
+        self.files.push(Written {
+            path: shown.clone(),
+            size: contents.len() as u64,
+            mode: self.fs.mode(path).unwrap_or(0o644),
+            sha256: manifest::sha256(contents),
+        });
+        self.created.push(shown.clone());
+        if !self.own.iter().any(|(p, _)| p == path) {
+            self.own.push((path.to_string(), shown));
+        }
+        Ok(())
+    }
+
+    /// Create the directory `path` if it doesn't exist yet.
+    pub fn create_dir(&mut self, path: &str) {
+        let shown = self.display(path);
+        // the directory may already exist in the project
+        let exists = self.fs.exists(&shown);
+        if self.fs.create_dir(path).is_ok() && !exists {
+            self.created.push(shown);
+        }
+    }
+
+    /// What has been created so far, with paths relative to the project.
+    pub fn manifest(&self) -> Manifest {
+        let relative = |shown: &str| {
+            Path::new(shown)
+                .strip_prefix(&self.root)
+                .map(|p| p.to_string_lossy().replace('\\', "/"))
+                .unwrap_or_else(|_| shown.to_string())
+        };
+        let files: Vec<FileHash> = self
+            .files
+            .iter()
+            .map(|f| FileHash {
+                path: relative(&f.path),
+                sha256: f.sha256.clone(),
+            })
+            .collect();
+        let mut directories: Vec<String> = self
+            .created
+            .iter()
+            .filter(|c| !self.files.iter().any(|f| f.path == **c))
+            .map(|c| relative(c))
+            .collect();
+        directories.dedup();
+        Manifest {
+            created_directory: self.new_root,
+            directories,
+            files,
+        }
+    }
+
+    /// Whether a file or directory exists at `path`.
+    pub fn exists(&self, path: &str) -> bool {
+        self.fs.exists(path)
+    }
+
+    /// Mark the file at `path` as executable.
+    pub fn set_executable(&mut self, path: &str) -> io::Result<()> {
+        self.fs.set_executable(path)?;
+        let shown = self.display(path);
+        let mode = self.fs.mode(path)?;
+        if let Some(f) = self.files.iter_mut().find(|f| f.path == shown) {
+            f.mode = mode;
+        }
+        Ok(())
+    }
+
+    // The file that writing to `path` would replace: one written earlier, or
+    // one already in the target.
+    fn existing(&self, path: &str) -> Option<String> {
+        if self.fs.is_file(path) {
+            return Some(path.to_string());
+        }
+        let shown = self.display(path);
+        if shown != path && self.fs.is_file(&shown) {
+            Some(shown)
+        } else {
+            None
+        }
+    }
+
+    // A name to back up `path` to that isn't taken, in the stage or the
+    // target: `path.bak`, or else `path.bak.1`, `path.bak.2`, and so on.
+    fn backup_name(&self, path: &str) -> String {
+        let mut n = 0;
+        loop {
+            let mut backup = path.to_string();
+            backup.push_str(".bak");
+            if n > 0 {
+                backup.push('.');
+                backup.push_str(&n.to_string());
+            }
+            let shown = self.display(&backup);
+            let pending = self.renames.iter().any(|(_, to)| *to == shown);
+            if !self.fs.exists(&backup) && !self.fs.exists(&shown) && !pending {
+                return backup;
+            }
+            n += 1;
+        }
+    }
+
+    /// The error for failing to create the file at `path`, as it will appear
+    /// once the project is in place.
+    pub fn create_error(&self, path: &str, e: io::Error) -> PiError {
+        PiError::CreateFile(self.display(path), e)
+    }
+}
+
+fn parent_dir(path: &Path) -> PathBuf {
+    match path.parent() {
+        Some(p) if p != Path::new("") => p.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// Recursively copy the contents of `from` into the existing directory `to`.
+pub fn copy_dir(from: &Path, to: &Path) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            fs::create_dir(&dest)?;
+            fs::set_permissions(&dest, entry.metadata()?.permissions())?;
+            copy_dir(&entry.path(), &dest)?;
+        } else if file_type.is_symlink() {
+            copy_link(&entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
+        }
+    }
+    Ok(())
+}
+
+// Move everything in `from` over to `to`, replacing files that are there.
+// Each path moved to is recorded, along with where in `saved` whatever it
+// replaced was put.
+fn replace_dir(
+    from: &Path,
+    to: &Path,
+    saved: &Path,
+    moved: &mut Vec<(PathBuf, Option<PathBuf>)>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        let existing = fs::symlink_metadata(&dest).ok();
+        if entry.file_type()?.is_dir() && existing.as_ref().map(|m| m.is_dir()) == Some(true) {
+            replace_dir(&entry.path(), &dest, saved, moved)?;
+        } else {
+            replace_file(&entry.path(), &dest, saved, moved)?;
+        }
+    }
+    Ok(())
+}
+
+// Move `from` to `to`, putting whatever is at `to` in `saved` first. Records
+// the move as `replace_dir` does.
+fn replace_file<P: AsRef<Path>>(
+    from: &Path,
+    to: P,
+    saved: &Path,
+    moved: &mut Vec<(PathBuf, Option<PathBuf>)>,
+) -> io::Result<()> {
+    let to = to.as_ref();
+    let original = if fs::symlink_metadata(to).is_ok() {
+        let keep = saved.join(moved.len().to_string());
+        fs::rename(to, &keep)?;
+        Some(keep)
+    } else {
+        None
+    };
+    moved.push((to.to_path_buf(), original));
+    fs::rename(from, to)
+}
+
+// Undo the moves recorded by `replace_dir`, putting back what was replaced.
+fn restore(moved: &[(PathBuf, Option<PathBuf>)]) {
+    for (dest, original) in moved.iter().rev() {
+        let _ = if dest.is_dir() {
+            fs::remove_dir_all(dest)
+        } else {
+            fs::remove_file(dest)
+        };
+        if let Some(original) = original {
+            let _ = fs::rename(original, dest);
+        }
+    }
+}
+
+// Move everything in `from` that doesn't exist in `to` over, recording what
+// was created.
+fn merge_dir(from: &Path, to: &Path, created: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if fs::symlink_metadata(&dest).is_err() {
+            fs::rename(entry.path(), &dest)?;
+            created.push(dest);
+        } else if entry.file_type()?.is_dir() && dest.is_dir() {
+            merge_dir(&entry.path(), &dest, created)?;
+        }
+    }
+    Ok(())
+}
+
 #[cfg(not(target_os = "windows"))]
-use std::os::unix::fs::PermissionsExt;
-use std::process::*;
+fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(fs::read_link(from)?, to)
+}
+
+#[cfg(target_os = "windows")]
+fn copy_link(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to).map(|_| ())
+}
+
+fn print_diff(path: &str, old: &[u8], new: &[u8], stderr: bool) {
+    let mut out = String::new();
+    match (std::str::from_utf8(old), std::str::from_utf8(new)) {
+        (Ok(o), Ok(n)) => {
+            let diff = TextDiff::from_lines(o, n);
+            let mut a = "a/".to_string();
+            a.push_str(path);
+            let mut b = "b/".to_string();
+            b.push_str(path);
+            for line in diff.unified_diff().header(&a, &b).to_string().lines() {
+                let line = if line.starts_with("+++") || line.starts_with("---") {
+                    line.bold()
+                } else if line.starts_with('+') {
+                    line.green()
+                } else if line.starts_with('-') {
+                    line.red()
+                } else if line.starts_with('@') {
+                    line.cyan()
+                } else {
+                    line.normal()
+                };
+                out.push_str(&format!("{}\n", line));
+            }
+        }
+        _ => out.push_str(&format!("Binary file {} differs\n", path)),
+    }
+    if stderr {
+        eprint!("{}", out);
+    } else {
+        print!("{}", out);
+    }
+}
+
+fn confirm(path: &str, stderr: bool) -> bool {
+    if stderr {
+        eprint!("Overwrite {}? [y/N] ", path);
+        let _ = io::stderr().flush();
+    } else {
+        print!("Overwrite {}? [y/N] ", path);
+        let _ = io::stdout().flush();
+    }
+    let mut answer = String::new();
+    if io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    let answer = answer.trim().to_lowercase();
+    answer == "y" || answer == "yes"
+}
 
 /// Trait allowing us to create dirs/templates/files.
 pub trait Create {
-    fn create_dirs(&self, name: &str) -> ();
+    fn create_dirs(&self, name: &str, writer: &mut Writer) -> ();
+}
+
+// Render a mustache template held in a string.
+fn render_str(template: &str, hash: &HashBuilder) -> std::result::Result<String, PiError> {
+    let mut o = Cursor::new(Vec::new());
+    hash.render(template, &mut o)
+        .map_err(|e| PiError::Render(template.to_string(), e.to_string()))?;
+    Ok(String::from_utf8(o.into_inner()).unwrap())
 }
 
 /// Render a list of directories, substituting in templates
-pub fn render_dirs(dirs_pre: Vec<String>, hash: &HashBuilder, name: &str) {
+pub fn render_dirs(
+    dirs_pre: Vec<String>,
+    hash: &HashBuilder,
+    name: &str,
+    writer: &mut Writer,
+) -> std::result::Result<(), PiError> {
     // substitute into directory names using templates
-    let dirs: Vec<String> = dirs_pre
-        .into_iter()
-        .map(|file| {
-            let mut o = Cursor::new(Vec::new());
-            hash.render(&file, &mut o).unwrap();
-            String::from_utf8(o.into_inner()).unwrap()
-        })
-        .collect();
+    let dirs = dirs_pre
+        .iter()
+        .map(|dir| render_str(dir, hash))
+        .collect::<std::result::Result<Vec<String>, PiError>>()?;
 
     // create directories
-    dirs.create_dirs(name);
+    dirs.create_dirs(name, writer);
+    Ok(())
 }
 
 /// Create all the files, and return a list of files that have been created
 /// suitable for insertion
 /// into a `HashBuilder`
-pub fn render_files<'a>(files_pre: Vec<String>, hash: &HashBuilder, name: &str) -> VecBuilder<'a> {
+pub fn render_files<'a>(
+    files_pre: Vec<String>,
+    hash: &HashBuilder,
+    name: &str,
+    writer: &mut Writer,
+) -> std::result::Result<VecBuilder<'a>, PiError> {
     // render filenames
-    let substitutions: Vec<String> = files_pre
-        .into_iter()
-        .map(|file| {
-            let mut o = Cursor::new(Vec::new());
-            hash.render(&file, &mut o).unwrap();
-            String::from_utf8(o.into_inner()).unwrap()
-        })
-        .collect();
+    let substitutions = files_pre
+        .iter()
+        .map(|file| render_str(file, hash))
+        .collect::<std::result::Result<Vec<String>, PiError>>()?;
 
     // write files
-    let _ = substitutions
-        .clone()
-        .into_iter()
-        .map(|path| {
-            let mut full_path = name.to_string();
-            full_path.push('/');
-            full_path.push_str(&path);
-            File::create(full_path)
-        })
-        .count();
+    for path in &substitutions {
+        let mut full_path = name.to_string();
+        full_path.push('/');
+        full_path.push_str(path);
+        if let Err(e) = writer.write(&full_path, b"") {
+            return Err(writer.create_error(&full_path, e));
+        }
+    }
 
     // collect filenames
     let s: Vec<Data> = substitutions.into_iter().map(Data::from).collect();
 
     // return a `VecBuilder` object.
-    VecBuilder { data: s }
+    Ok(VecBuilder { data: s })
 }
 
 /// Create directories given a Vec<String> of directory names
 impl<T: ToString> Create for Vec<T> {
-    fn create_dirs(&self, name: &str) {
+    fn create_dirs(&self, name: &str, writer: &mut Writer) {
         self.iter()
             .map(|dir| {
                 let mut subdir = name.to_string();
                 subdir.push('/');
                 subdir.push_str(&dir.to_string());
-                fs::create_dir(subdir).unwrap_or(());
+                writer.create_dir(&subdir);
             })
             .count();
     }
 }
 
+/// Templates are rendered and written in batches of this many files, which
+/// bounds how much rendered output is held in memory at once.
+const BATCH_SIZE: usize = 256;
+
+/// Templates with fewer files than this are rendered on the calling thread;
+/// spawning workers would cost more than it saves.
+const PARALLEL_THRESHOLD: usize = 32;
+
 /// render a `<Vec<String>>` of templates, doing nothing if it's empty.
-#[cfg(target_os = "windows")]
+///
+/// Large templates are rendered on a pool of worker threads. Files are still
+/// written one at a time and in the order they are listed, so the output (and
+/// any conflict prompts) is the same as for a sequential run.
 pub fn render_templates(
     project: &str,
     name: &str,
     hash: &HashBuilder,
     templates_pre: Option<Vec<String>>,
     executable: bool,
-) {
+    writer: &mut Writer,
+) -> std::result::Result<(), PiError> {
     if let Some(t) = templates_pre {
-        // create Vec<T> of paths to templates
-        let templates: Vec<String> = t
-            .into_iter()
-            .map(|file| {
-                let mut p = project.to_string();
-                p.push('/');
-                p.push_str(&file);
-                if executable {
-                    p.push_str(".bat");
-                }
-                p
-            })
-            .collect();
-
-        // read all the template files
-        let template_files: Vec<String> = templates
-            .into_iter()
-            .map(|p| {
-                let template_f_pre = File::open(&p);
-                let mut t = String::new();
-                let mut template_f = if let Ok(f) = template_f_pre {
-                    f
-                } else {
-                    eprintln!("Failed to open file: {:?}", p);
-                    exit(0x0f00);
-                };
-                template_f
-                    .read_to_string(&mut t)
-                    .expect("File read failed."); // ok to panic because we already errored.
-                t
-            })
-            .collect();
+        let jobs = if t.len() < PARALLEL_THRESHOLD {
+            1
+        } else {
+            thread::available_parallelism()
+                .map(|n| n.get())
+                .unwrap_or(1)
+        };
+        let values = if jobs > 1 {
+            hash_values(hash)
+        } else {
+            Vec::new()
+        };
 
-        // create Vec<T> of paths to rendered templates
-        let templates_new: Vec<String> = t
-            .into_iter()
-            .map(|file| {
-                let mut p = name.to_string();
-                p.push('/');
-                p.push_str(&file);
-                p
-            })
-            .collect();
+        for batch in t.chunks(BATCH_SIZE) {
+            let rendered = if jobs > 1 {
+                render_parallel(project, batch, &values, executable, jobs)
+            } else {
+                batch
+                    .iter()
+                    .map(|file| render_template(project, file, hash, executable))
+                    .collect()
+            };
 
-        // subtitute into template names
-        let templates_named: Vec<String> = templates_new
-            .into_iter()
-            .map(|n| {
-                let mut o = Cursor::new(Vec::new());
-                hash.render(&n, &mut o).unwrap();
-                String::from_utf8(o.into_inner()).unwrap()
-            })
-            .collect();
-
-        // render all the template files
-        let s: Vec<String> = template_files
-            .into_iter()
-            .map(|file| {
-                let mut o = Cursor::new(Vec::new());
-                hash.render(&file, &mut o).unwrap();
-                String::from_utf8(o.into_inner()).unwrap()
-            })
-            .collect();
-
-        // write the rendered templates
-        let files_to_write = templates_named.iter().zip(s.iter());
-        let _ = files_to_write
-            .into_iter()
-            .map(|(path, contents)| {
-                let c = File::create(&path);
-                if let Ok(mut f) = c {
-                    let _ = f.write(contents.as_bytes());
-                } else {
-                    eprintln!("Failed to create file: {:?}. Check that the directory is included in your template.toml", path);
-                    exit(0x0f01);
-                };
-            })
-            .count();
+            // write the rendered templates
+            for r in rendered {
+                let (file, contents) = r?;
+                let mut path = name.to_string();
+                path.push('/');
+                path.push_str(&file);
+                let written = writer.write(&path, contents.as_bytes()).and_then(|w| {
+                    if executable && w == Outcome::Written {
+                        writer.set_executable(&path)
+                    } else {
+                        Ok(())
+                    }
+                });
+                if let Err(e) = written {
+                    return Err(writer.create_error(&path, e));
+                }
+            }
+        }
     }
+    Ok(())
 }
 
-/// render a `<Vec<String>>` of templates, doing nothing if it's empty.
-#[cfg(not(target_os = "windows"))]
-pub fn render_templates(
+// Read and render a single template, returning the rendered file name and
+// contents.
+fn render_template(
     project: &str,
-    name: &str,
+    file: &str,
     hash: &HashBuilder,
-    templates_pre: Option<Vec<String>>,
     executable: bool,
-) {
-    if let Some(t) = templates_pre {
-        // create Vec<T> of paths to templates
-        let templates: Vec<String> = t
-            .clone()
-            .into_iter()
-            .map(|file| {
-                let mut p = project.to_string();
-                p.push('/');
-                p.push_str(&file);
-                p
-            })
-            .collect();
+) -> std::result::Result<(String, String), PiError> {
+    let source = template_source(project, file, executable);
+    let mut template = String::new();
+    if File::open(&source)
+        .and_then(|mut f| f.read_to_string(&mut template))
+        .is_err()
+    {
+        return Err(PiError::MissingTemplateFile(source));
+    }
 
-        // read all the template files
-        let template_files: Vec<String> = templates
-            .into_iter()
-            .map(|p| {
-                let template_f_pre = File::open(&p);
-                let mut t = String::new();
-                let mut template_f = if let Ok(f) = template_f_pre {
-                    f
-                } else {
-                    eprintln!("Failed to open file: {:?}", p);
-                    exit(0x0f01);
-                };
-                template_f
-                    .read_to_string(&mut t)
-                    .expect("File read failed."); // ok to panic because we already errored.
-                t
-            })
-            .collect();
+    let name = render_str(file, hash)?;
+    let contents = render_str(&template, hash).map_err(|e| match e {
+        PiError::Render(_, msg) => PiError::Render(source, msg),
+        e => e,
+    })?;
+    Ok((name, contents))
+}
 
-        // create Vec<T> of paths to rendered templates
-        let templates_new: Vec<String> = t
-            .into_iter()
-            .map(|file| {
-                let mut p = name.to_string();
-                p.push('/');
-                p.push_str(&file);
-                p
+// Render a batch of templates on `jobs` threads, returning the results in the
+// same order as `batch`.
+fn render_parallel(
+    project: &str,
+    batch: &[String],
+    values: &[(String, Value)],
+    executable: bool,
+    jobs: usize,
+) -> Vec<std::result::Result<(String, String), PiError>> {
+    let next = AtomicUsize::new(0);
+    let mut results: Vec<_> = thread::scope(|s| {
+        let workers: Vec<_> = (0..jobs.min(batch.len()))
+            .map(|_| {
+                s.spawn(|| {
+                    // `HashBuilder` can't cross threads, so each worker builds its own.
+                    let hash = hash_from_values(values);
+                    let mut done = Vec::new();
+                    loop {
+                        let i = next.fetch_add(1, Ordering::SeqCst);
+                        if i >= batch.len() {
+                            break;
+                        }
+                        done.push((i, render_template(project, &batch[i], &hash, executable)));
+                    }
+                    done
+                })
             })
             .collect();
-
-        // subtitute into template names
-        let templates_named: Vec<String> = templates_new
+        workers
             .into_iter()
-            .map(|n| {
-                let mut o = Cursor::new(Vec::new());
-                hash.render(&n, &mut o).unwrap();
-                String::from_utf8(o.into_inner()).unwrap()
-            })
-            .collect();
+            .flat_map(|w| w.join().expect("template worker panicked"))
+            .collect()
+    });
+    results.sort_by_key(|r| r.0);
+    results.into_iter().map(|r| r.1).collect()
+}
 
-        // render all the template files
-        let s: Vec<String> = template_files
-            .into_iter()
-            .map(|file| {
-                let mut o = Cursor::new(Vec::new());
-                hash.render(&file, &mut o).unwrap();
-                String::from_utf8(o.into_inner()).unwrap()
-            })
-            .collect();
+/// Owned copy of the data in a `HashBuilder`, which unlike `Data` can be
+/// shared between threads.
+enum Value {
+    Str(String),
+    Bool(bool),
+    Int(i32),
+    Float(f64),
+    List(Vec<Value>),
+    Table(Vec<(String, Value)>),
+}
 
-        // write the rendered templates
-        let files_to_write = templates_named.iter().zip(s.iter());
-        let _ = files_to_write
-            .map(|(path, contents)| {
-                let c = File::create(&path);
-                if let Ok(mut f) = c {
-                    let _ = f.write(contents.as_bytes());
-                } else {
-                    eprintln!("Failed to create file: {:?}. Check that the directory is included in your template.toml", path);
-                    exit(0x0f01);
-                };
+impl Value {
+    // Lambdas can't be copied; we never insert any.
+    fn from_data(d: &Data) -> Option<Value> {
+        match *d {
+            Data::String(ref s) => Some(Value::Str(s.clone())),
+            Data::Bool(b) => Some(Value::Bool(b)),
+            Data::Integer(i) => Some(Value::Int(i)),
+            Data::Float(f) => Some(Value::Float(f)),
+            Data::Vector(ref v) => {
+                Some(Value::List(v.iter().filter_map(Value::from_data).collect()))
+            }
+            Data::Hash(ref h) => Some(Value::Table(
+                h.iter()
+                    .filter_map(|(k, v)| Value::from_data(v).map(|v| (k.clone(), v)))
+                    .collect(),
+            )),
+            Data::Lambda(_) => None,
+        }
+    }
 
-                if executable {
-                    let mut p = fs::metadata(path)
-                        .expect("failed to read file metadata")
-                        .permissions();
-                    p.set_mode(0o755);
-                    let _ = fs::set_permissions(path, p);
-                };
-            })
-            .count();
+    fn to_data(&self) -> Data<'static> {
+        match *self {
+            Value::Str(ref s) => Data::String(s.clone()),
+            Value::Bool(b) => Data::Bool(b),
+            Value::Int(i) => Data::Integer(i),
+            Value::Float(f) => Data::Float(f),
+            Value::List(ref v) => Data::Vector(v.iter().map(Value::to_data).collect()),
+            Value::Table(ref t) => {
+                Data::Hash(t.iter().map(|(k, v)| (k.clone(), v.to_data())).collect())
+            }
+        }
+    }
+}
+
+fn hash_values(hash: &HashBuilder) -> Vec<(String, Value)> {
+    hash.data
+        .iter()
+        .filter_map(|(k, v)| Value::from_data(v).map(|v| (k.clone(), v)))
+        .collect()
+}
+
+fn hash_from_values(values: &[(String, Value)]) -> HashBuilder<'static> {
+    let mut hash = HashBuilder::new();
+    for (k, v) in values {
+        hash.data.insert(k.clone(), v.to_data());
+    }
+    hash
+}
+
+// Path of the template file to read. On Windows, scripts are read from a
+// `.bat` file alongside the template.
+fn template_source(project: &str, file: &str, executable: bool) -> String {
+    let mut p = project.to_string();
+    p.push('/');
+    p.push_str(file);
+    if cfg!(target_os = "windows") && executable {
+        p.push_str(".bat");
     }
+    p
 }
 
 /// Function to write a file from a static string
-pub fn create_file(static_contents: &'static str, name: &str, filename: &str) {
+pub fn create_file(
+    static_contents: &'static str,
+    name: &str,
+    filename: &str,
+    writer: &mut Writer,
+) -> std::result::Result<(), PiError> {
+    let mut p = name.to_string();
+    p.push('/');
+    p.push_str(filename);
+    match writer.write(&p, static_contents.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(writer.create_error(&p, e)),
+    }
+}
+
+/// Write one of pi's own files, which isn't subject to the conflict policy.
+pub fn write_own_file(
+    contents: &str,
+    name: &str,
+    filename: &str,
+    writer: &mut Writer,
+) -> std::result::Result<(), PiError> {
     let mut p = name.to_string();
     p.push('/');
     p.push_str(filename);
-    let mut c = File::create(p).expect("File creation failed."); // ok to panic because this is for built-ins.
-    let _ = c.write(static_contents.as_bytes());
+    writer
+        .write_own(&p, contents.as_bytes())
+        .map_err(|e| writer.create_error(&p, e))
 }
 
 /// Write a file from a static string
-pub fn write_file_plain(static_contents: &'static str, name: &str, filename: &str) {
+pub fn write_file_plain(
+    static_contents: &str,
+    name: &str,
+    filename: &str,
+    writer: &mut Writer,
+) -> std::result::Result<(), PiError> {
     // write the file
     let mut p = name.to_string();
     p.push('/');
     p.push_str(filename);
 
     // write the rendered template
-    let mut c = File::create(p).expect("File creation failed."); // ok to panic because this is for built-ins.
-    let _ = c.write(static_contents.as_bytes());
+    match writer.write(&p, static_contents.as_bytes()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(writer.create_error(&p, e)),
+    }
 }
 
 /// Render a static string and write it to file
-pub fn render_file(static_template: &'static str, name: &str, filename: &str, hash: &HashBuilder) {
+pub fn render_file(
+    static_template: &'static str,
+    name: &str,
+    filename: &str,
+    hash: &HashBuilder,
+    writer: &mut Writer,
+) -> std::result::Result<Outcome, PiError> {
     // render the template
-    let mut o = Cursor::new(Vec::new());
-    hash.render(static_template, &mut o).unwrap();
-    let contents = String::from_utf8(o.into_inner()).unwrap();
+    let contents = render_str(static_template, hash).map_err(|e| match e {
+        PiError::Render(_, msg) => PiError::Render(filename.to_string(), msg),
+        e => e,
+    })?;
 
     // write the file
     let mut p = name.to_string();
@@ -299,14 +1083,238 @@ pub fn render_file(static_template: &'static str, name: &str, filename: &str, ha
     p.push_str(filename);
 
     // write the rendered template
-    let c = File::create(&p);
-    if let Ok(mut f) = c {
-        let _ = f.write(contents.as_bytes());
-    } else {
-        eprintln!(
-            "Failed to create file: {:?}. Check that the directory is included in your template.toml",
-            p
+    writer
+        .write(&p, contents.as_bytes())
+        .map_err(|e| writer.create_error(&p, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive::Entry;
+
+    fn apply(text: TextOptions, contents: &str) -> String {
+        String::from_utf8(text.apply(contents.as_bytes()).into_owned()).unwrap()
+    }
+
+    fn endings(le: LineEndings) -> TextOptions {
+        TextOptions {
+            line_endings: Some(le),
+            ..TextOptions::default()
+        }
+    }
+
+    #[test]
+    fn text_defaults_leave_contents_alone() {
+        let contents = "a\r\nb\n\n";
+        assert!(matches!(
+            TextOptions::default().apply(contents.as_bytes()),
+            Cow::Borrowed(_)
+        ));
+        assert_eq!(apply(TextOptions::default(), contents), contents);
+    }
+
+    #[test]
+    fn text_line_endings() {
+        assert_eq!(apply(endings(LineEndings::Lf), "a\r\nb\nc"), "a\nb\nc");
+        assert_eq!(
+            apply(endings(LineEndings::Crlf), "a\r\nb\nc"),
+            "a\r\nb\r\nc"
         );
-        exit(0x0f01);
+        let native = if cfg!(target_os = "windows") {
+            "a\r\nb"
+        } else {
+            "a\nb"
+        };
+        assert_eq!(apply(endings(LineEndings::Native), "a\r\nb"), native);
+        assert_eq!("crlf".parse::<LineEndings>(), Ok(LineEndings::Crlf));
+        assert!("cr".parse::<LineEndings>().is_err());
+    }
+
+    #[test]
+    fn text_trailing_newline() {
+        let add = TextOptions {
+            trailing_newline: Some(true),
+            ..TextOptions::default()
+        };
+        assert_eq!(apply(add, "a"), "a\n");
+        assert_eq!(apply(add, "a\n"), "a\n");
+        // matches the line endings already in the file
+        assert_eq!(apply(add, "a\r\nb"), "a\r\nb\r\n");
+        let add_crlf = TextOptions {
+            line_endings: Some(LineEndings::Crlf),
+            ..add
+        };
+        assert_eq!(apply(add_crlf, "a\nb"), "a\r\nb\r\n");
+        let remove = TextOptions {
+            trailing_newline: Some(false),
+            ..TextOptions::default()
+        };
+        assert_eq!(apply(remove, "a\r\n\n\n"), "a");
+        assert_eq!(apply(remove, "a"), "a");
+    }
+
+    #[test]
+    fn text_bom() {
+        let add = TextOptions {
+            bom: Some(true),
+            ..TextOptions::default()
+        };
+        assert_eq!(apply(add, "a"), "\u{feff}a");
+        assert_eq!(apply(add, "\u{feff}a"), "\u{feff}a");
+        let remove = TextOptions {
+            bom: Some(false),
+            ..TextOptions::default()
+        };
+        assert_eq!(apply(remove, "\u{feff}a"), "a");
+        // kept when only other options are given
+        assert_eq!(
+            apply(endings(LineEndings::Lf), "\u{feff}a\r\n"),
+            "\u{feff}a\n"
+        );
+    }
+
+    #[test]
+    fn text_skips_binary_and_empty_files() {
+        let all = TextOptions {
+            line_endings: Some(LineEndings::Crlf),
+            trailing_newline: Some(true),
+            bom: Some(true),
+        };
+        let binary = [0xff, 0xfe, b'\n', 0x00];
+        assert_eq!(all.apply(&binary).as_ref(), &binary[..]);
+        assert_eq!(all.apply(b"").as_ref(), b"");
+    }
+
+    #[test]
+    fn text_or_prefers_its_own_options() {
+        let cli = TextOptions {
+            bom: Some(false),
+            ..TextOptions::default()
+        };
+        let template = TextOptions {
+            line_endings: Some(LineEndings::Crlf),
+            bom: Some(true),
+            ..TextOptions::default()
+        };
+        assert_eq!(
+            cli.or(template),
+            TextOptions {
+                line_endings: Some(LineEndings::Crlf),
+                trailing_newline: None,
+                bom: Some(false),
+            }
+        );
+    }
+
+    #[test]
+    fn stage_creates_the_project_in_memory() {
+        let mut writer = Writer::new(Force::Overwrite, false);
+        let memory = writer.in_memory();
+        let root = writer.stage("some/where/proj").unwrap();
+        assert_eq!(root, "proj");
+        writer.write("proj/README.md", b"hi").unwrap();
+        writer.create_dir("proj/src");
+        writer.write("proj/src/lib.rs", b"").unwrap();
+        assert!(writer.write("proj/missing/lib.rs", b"").is_err());
+        let entries = memory.entries();
+        assert_eq!(entries.get("proj"), Some(&Entry::Dir));
+        assert_eq!(entries.get("proj/src"), Some(&Entry::Dir));
+        assert!(memory.is_file("proj/README.md"));
+        assert!(!memory.exists("proj/missing"));
+    }
+
+    // A writer with `proj/a` already rendered in memory.
+    fn writer_with_file(force: Force) -> (Writer, Memory) {
+        let mut writer = Writer::new(force, false);
+        let memory = writer.in_memory();
+        writer.stage("proj").unwrap();
+        writer.write("proj/a", b"old").unwrap();
+        (writer, memory)
+    }
+
+    fn contents(memory: &Memory, path: &str) -> Vec<u8> {
+        memory.read_file(path).unwrap()
+    }
+
+    #[test]
+    fn write_leaves_unchanged_files() {
+        let (mut writer, _) = writer_with_file(Force::Skip);
+        assert_eq!(writer.write("proj/a", b"old").unwrap(), Outcome::Unchanged);
+        assert!(writer.skipped.is_empty());
+    }
+
+    #[test]
+    fn write_skips_existing_files() {
+        let (mut writer, memory) = writer_with_file(Force::Skip);
+        assert_eq!(writer.write("proj/a", b"new").unwrap(), Outcome::Kept);
+        assert_eq!(contents(&memory, "proj/a"), b"old");
+        assert_eq!(writer.skipped, vec!["proj/a".to_string()]);
+    }
+
+    #[test]
+    fn write_overwrites_existing_files() {
+        let (mut writer, memory) = writer_with_file(Force::Overwrite);
+        assert_eq!(writer.write("proj/a", b"new").unwrap(), Outcome::Written);
+        assert_eq!(contents(&memory, "proj/a"), b"new");
+        assert!(writer.backups.is_empty());
+    }
+
+    #[test]
+    fn write_numbers_backups() {
+        let (mut writer, memory) = writer_with_file(Force::Backup);
+        assert_eq!(writer.write("proj/a", b"new").unwrap(), Outcome::Written);
+        assert_eq!(writer.write("proj/a", b"newer").unwrap(), Outcome::Written);
+        assert_eq!(contents(&memory, "proj/a"), b"newer");
+        assert_eq!(contents(&memory, "proj/a.bak"), b"old");
+        assert_eq!(contents(&memory, "proj/a.bak.1"), b"new");
+        assert_eq!(
+            writer.backups,
+            vec!["proj/a.bak".to_string(), "proj/a.bak.1".to_string()]
+        );
+    }
+
+    // A project directory holding `a` and `mine`, in a temporary directory.
+    fn existing_project() -> (TempDir, String) {
+        let dir = TempDir::new("pi-render").unwrap();
+        let target = dir.path().join("proj");
+        fs::create_dir(&target).unwrap();
+        fs::write(target.join("a"), "old").unwrap();
+        fs::write(target.join("mine"), "mine").unwrap();
+        let target = target.to_string_lossy().to_string();
+        (dir, target)
+    }
+
+    #[test]
+    fn commit_keeps_other_files() {
+        let (_dir, target) = existing_project();
+        let mut writer = Writer::new(Force::Overwrite, false);
+        let root = writer.stage(&target).unwrap();
+        writer.write(&format!("{}/a", root), b"new").unwrap();
+        writer.write(&format!("{}/b", root), b"b").unwrap();
+        writer.commit().unwrap();
+        let target = Path::new(&target);
+        assert_eq!(fs::read_to_string(target.join("a")).unwrap(), "new");
+        assert_eq!(fs::read_to_string(target.join("b")).unwrap(), "b");
+        assert_eq!(fs::read_to_string(target.join("mine")).unwrap(), "mine");
+    }
+
+    #[test]
+    fn failed_commit_leaves_the_target_alone() {
+        let (_dir, target) = existing_project();
+        fs::write(Path::new(&target).join("c"), "old").unwrap();
+        let mut writer = Writer::new(Force::Backup, false);
+        let root = writer.stage(&target).unwrap();
+        writer.write(&format!("{}/a", root), b"new").unwrap();
+        writer.write(&format!("{}/b", root), b"b").unwrap();
+        writer.write(&format!("{}/c", root), b"new").unwrap();
+        // `a` is backed up before `c` turns out to be gone
+        fs::remove_file(Path::new(&target).join("c")).unwrap();
+        assert!(writer.commit().is_err());
+        let target = Path::new(&target);
+        assert_eq!(fs::read_to_string(target.join("a")).unwrap(), "old");
+        assert_eq!(fs::read_to_string(target.join("mine")).unwrap(), "mine");
+        assert!(!target.join("a.bak").exists());
+        assert!(!target.join("b").exists());
     }
 }
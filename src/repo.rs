@@ -1,87 +1,152 @@
 use colored::*;
 use std;
-use std::process::Command;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Stdio};
 
-pub fn git_init(name: &str) {
-    let mut cmd = "cd ".to_string();
-    cmd.push_str(name);
-    cmd.push_str("&&");
-    cmd.push_str("git init && git add *");
-    if let Ok(c) = Command::new("sh")
+/// Initialize the version control system `vc` inside directory `name`.
+/// Unsupported tools are reported and otherwise ignored.
+pub fn init(vc: &str, name: &str) -> io::Result<()> {
+    match vc {
+        "git" => git_init(name),
+        "hg" | "mercurial" => hg_init(name),
+        "pijul" => pijul_init(name),
+        "darcs" => darcs_init(name),
+        _ => {
+            eprintln!(
+                "{}: version control {} is not yet supported. Supported version control tools are darcs, pijul, mercurial, and git.",
+                "Error".red(),
+                vc
+            );
+            Ok(())
+        }
+    }
+}
+
+fn run(cmd: String, tool: &str) -> io::Result<()> {
+    let status = Command::new("sh")
         .arg("-c")
         .arg(cmd)
         .stdout(std::process::Stdio::null())
-        .spawn()
-    {
-        c.wait_with_output().expect("failed to wait on child");
+        .status()?;
+    if status.success() {
+        Ok(())
     } else {
-        eprintln!(
-            "{}, git failed to initialize. Is git on your path?",
-            "Error".red()
-        );
-        std::process::exit(0x0f01);
+        Err(io::Error::other(format!(
+            "{} exited with {}. Is it on your path?",
+            tool, status
+        )))
     }
 }
 
-pub fn pijul_init(name: &str) {
+pub fn git_init(name: &str) -> io::Result<()> {
+    let mut cmd = "cd ".to_string();
+    cmd.push_str(name);
+    cmd.push_str("&&");
+    cmd.push_str("git init && git add *");
+    run(cmd, "git")
+}
+
+pub fn pijul_init(name: &str) -> io::Result<()> {
     let mut cmd = "cd ".to_string();
     cmd.push_str(name);
     cmd.push_str("&&");
     cmd.push_str("pijul init && pijul add **");
-    if let Ok(c) = Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
-        .stdout(std::process::Stdio::null())
-        .spawn()
-    {
-        c.wait_with_output().expect("failed to wait on child");
-    } else {
-        eprintln!(
-            "{}, Pijul failed to initialize. Is it on your path?",
-            "Error".red()
-        );
-        std::process::exit(0x0f01);
-    }
+    run(cmd, "pijul")
 }
 
-pub fn darcs_init(name: &str) {
+pub fn darcs_init(name: &str) -> io::Result<()> {
     let mut cmd = "cd ".to_string();
     cmd.push_str(name);
     cmd.push_str("&&");
     cmd.push_str("darcs init && darcs add **");
-    if let Ok(c) = Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
-        .stdout(std::process::Stdio::null())
-        .spawn()
-    {
-        c.wait_with_output().expect("failed to wait on child");
-    } else {
-        eprintln!(
-            "{}, Darcs failed to initialize. Is hg on your path?",
-            "Error".red()
-        );
-        std::process::exit(0x0f01);
-    }
+    run(cmd, "darcs")
 }
 
-pub fn hg_init(name: &str) {
+pub fn hg_init(name: &str) -> io::Result<()> {
     let mut cmd = "cd ".to_string();
     cmd.push_str(name);
     cmd.push_str("&&");
     cmd.push_str("hg init && hg add *");
-    if let Ok(c) = Command::new("sh")
-        .arg("-c")
-        .arg(cmd)
-        .stdout(std::process::Stdio::null())
-        .spawn()
-    {
-        c.wait_with_output().expect("failed to wait on child");
+    run(cmd, "hg")
+}
+
+/// Clone the `vc` repository at `url` into `dest`, which mustn't exist yet,
+/// at `revision` if given: a revision or tag for mercurial, a tag or patch
+/// hash for darcs, and a channel for pijul.
+pub fn clone(vc: &str, url: &str, revision: Option<&str>, dest: &Path) -> io::Result<()> {
+    // the URL is passed as is, rather than through a shell
+    let mut cmd = Command::new(vc);
+    cmd.arg("clone");
+    match (vc, revision) {
+        ("hg", Some(rev)) => {
+            cmd.arg("--updaterev").arg(rev);
+        }
+        ("darcs", Some(rev)) if is_hash(rev) => {
+            cmd.arg("--to-hash").arg(rev);
+        }
+        ("darcs", Some(rev)) => {
+            cmd.arg("--tag").arg(rev);
+        }
+        ("pijul", Some(rev)) => {
+            cmd.arg("--channel").arg(rev);
+        }
+        _ => (),
+    }
+    let output = cmd
+        .arg(url)
+        .arg(dest)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(e.kind(), format!("{} is not installed", vc)),
+            _ => e,
+        })?;
+    if output.status.success() {
+        Ok(())
     } else {
-        eprintln!(
-            "{}, Mercurial failed to initialize. Is it on your path?",
-            "Error".red()
-        );
-        std::process::exit(0x0f01);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        Err(io::Error::other(format!(
+            "{} exited with {}: {}",
+            vc,
+            output.status,
+            stderr.trim()
+        )))
+    }
+}
+
+/// The revision checked out in the `vc` repository `dir`, if it can be
+/// found: the changeset for mercurial and the last patch for darcs. Pijul
+/// has no single identifier to check out again, so there is none.
+pub fn revision(vc: &str, dir: &Path) -> Option<String> {
+    let args: &[&str] = match vc {
+        "hg" => &["log", "--rev", ".", "--template", "{node}"],
+        "darcs" => &["log", "--last", "1"],
+        _ => return None,
+    };
+    let output = Command::new(vc)
+        .args(args)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
     }
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let rev = match vc {
+        // `patch <hash>` comes first
+        "darcs" => stdout.lines().next()?.strip_prefix("patch ")?.trim(),
+        _ => stdout.trim(),
+    };
+    if rev.is_empty() {
+        None
+    } else {
+        Some(rev.to_string())
+    }
+}
+
+fn is_hash(rev: &str) -> bool {
+    rev.len() >= 40 && rev.chars().all(|c| c.is_ascii_hexdigit())
 }
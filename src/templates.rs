@@ -0,0 +1,419 @@
+//! Module for the directories user templates are looked up in, and for
+//! installing templates into them.
+use answers::Kind;
+use archive;
+use catalog;
+use error::PiError;
+use git2::Repository;
+use manifest::sha256;
+use remote;
+use render::copy_dir;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tempdir::TempDir;
+use toml;
+use types::GitConfig;
+
+/// Name of the file recording where an installed template came from.
+pub const FILE: &str = ".pi-source.toml";
+
+/// Where an installed template came from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Origin {
+    /// `git`, `archive`, or `dir`.
+    pub kind: Kind,
+    /// URL of the repository, path or URL of the archive, or path of the
+    /// directory.
+    pub location: String,
+    /// The branch, tag, or commit asked for, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revision: Option<String>,
+    /// Directory of the repository the template is in, if not at its root.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
+    /// The commit, or the hash of the archive, that was installed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub installed: Option<String>,
+}
+
+impl Origin {
+    /// Work out where a template given on the command line comes from: an
+    /// archive if it looks like one, a local directory if there is one, and
+    /// otherwise a git repository, given as for `pi git`.
+    pub fn parse(source: &str) -> Result<Origin, PiError> {
+        let (kind, location, revision, subdir) = if archive::Format::from_path(source).is_some() {
+            let location = if remote::is_http(source) {
+                source.to_string()
+            } else {
+                // in full, so updating works from anywhere
+                let full = Path::new(source)
+                    .canonicalize()
+                    .map_err(|_| PiError::MissingTemplate(source.to_string()))?;
+                full.to_string_lossy().to_string()
+            };
+            (Kind::Archive, location, None, None)
+        } else if Path::new(source).is_dir() {
+            (Kind::Dir, remote::absolute(source), None, None)
+        } else {
+            let (repo, revision) = remote::split_revision(source);
+            let (repo, subdir) = remote::split_subdir(repo);
+            (
+                Kind::Git,
+                remote::expand(repo),
+                revision.map(|r| r.to_string()),
+                subdir.map(|s| s.to_string()),
+            )
+        };
+        Ok(Origin {
+            kind,
+            location,
+            revision,
+            subdir,
+            installed: None,
+        })
+    }
+
+    /// Name to install the template under if none is given: the last part of
+    /// its directory, or of the repository or archive without extensions.
+    pub fn name(&self) -> String {
+        let path = self.subdir.as_deref().unwrap_or(&self.location);
+        let last = path
+            .trim_end_matches('/')
+            .rsplit(['/', ':'])
+            .next()
+            .unwrap_or("");
+        let lower = last.to_lowercase();
+        let extension = [".tar.gz", ".tgz", ".tar", ".zip", ".git"]
+            .iter()
+            .find(|e| lower.ends_with(*e))
+            .map(|e| e.len())
+            .unwrap_or(0);
+        last[..last.len() - extension].to_string()
+    }
+
+    /// The template as it would be given on the command line.
+    pub fn source(&self) -> String {
+        let mut s = self.location.clone();
+        if let Some(ref subdir) = self.subdir {
+            s.push_str("//");
+            s.push_str(subdir);
+        }
+        if let Some(ref revision) = self.revision {
+            s.push('@');
+            s.push_str(revision);
+        }
+        s
+    }
+}
+
+/// A template installed with `pi template install`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Installed {
+    pub name: String,
+    pub path: PathBuf,
+    pub origin: Origin,
+}
+
+/// The directories to look for user templates in, in order: those in
+/// `PI_TEMPLATE_PATH`, `$XDG_DATA_HOME/pi/templates`, `~/.pi_templates`, and
+/// `pi/templates` in each of `$XDG_DATA_DIRS` (by default `/usr/local/share`
+/// and `/usr/share`). A template in an earlier directory hides one of the
+/// same name in a later one.
+pub fn search_path(home: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = match env::var_os("PI_TEMPLATE_PATH") {
+        Some(p) => env::split_paths(&p)
+            .filter(|d| !d.as_os_str().is_empty())
+            .collect(),
+        None => Vec::new(),
+    };
+    // relative XDG directories are to be ignored
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .filter(|d| d.is_absolute())
+        .unwrap_or_else(|| home.join(".local").join("share"));
+    dirs.push(data_home.join("pi").join("templates"));
+    dirs.push(home.join(".pi_templates"));
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    for d in env::split_paths(&data_dirs).filter(|d| d.is_absolute()) {
+        dirs.push(d.join("pi").join("templates"));
+    }
+    dirs
+}
+
+/// The first directory in `search` containing `path`.
+pub fn find(path: &str, search: &[PathBuf]) -> Option<PathBuf> {
+    search.iter().find(|d| d.join(path).exists()).cloned()
+}
+
+/// The templates in `search`, by name, along with the directory each is in.
+/// Names starting with `.` or `_` are skipped.
+pub fn list(search: &[PathBuf]) -> Vec<(String, PathBuf)> {
+    let mut templates: Vec<(String, PathBuf)> = Vec::new();
+    for dir in search {
+        let mut names: Vec<String> = match fs::read_dir(dir) {
+            Ok(r) => r
+                .flatten()
+                .filter(|e| e.path().is_dir())
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|n| !n.starts_with('.') && !n.starts_with('_'))
+                .collect(),
+            Err(_) => continue,
+        };
+        names.sort();
+        for name in names {
+            // hidden by a template earlier in the search path
+            if templates.iter().any(|(n, _)| *n == name) {
+                continue;
+            }
+            templates.push((name, dir.clone()));
+        }
+    }
+    templates
+}
+
+/// The directory templates are installed into.
+pub fn user_dir(home: &Path) -> PathBuf {
+    home.join(".pi_templates")
+}
+
+/// Where the template in `dir` was installed from, if it was installed with
+/// `pi template install`.
+pub fn origin(dir: &Path) -> Option<Origin> {
+    let contents = fs::read_to_string(dir.join(FILE)).ok()?;
+    toml::from_str(&contents).ok()
+}
+
+/// Install the template from `origin` into the user template directory under
+/// `name`. `git` says how to authenticate.
+pub fn install(
+    name: &str,
+    origin: &Origin,
+    home: &Path,
+    git: &GitConfig,
+) -> Result<Installed, PiError> {
+    check_name(name)?;
+    let path = user_dir(home).join(name);
+    if fs::symlink_metadata(&path).is_ok() {
+        return Err(PiError::AlreadyInstalled(
+            name.to_string(),
+            path.to_string_lossy().to_string(),
+        ));
+    }
+    let (stage, origin) = stage(origin, home, git)?;
+    fs::rename(stage.path(), &path)?;
+    let _ = stage.into_path();
+    Ok(Installed {
+        name: name.to_string(),
+        path,
+        origin,
+    })
+}
+
+/// Everything installed with `pi template install`, by name.
+pub fn installed(home: &Path) -> Result<Vec<Installed>, PiError> {
+    let mut templates = Vec::new();
+    let read = match fs::read_dir(user_dir(home)) {
+        Ok(r) => r,
+        Err(_) => return Ok(templates),
+    };
+    for entry in read.flatten() {
+        let path = entry.path();
+        let file = path.join(FILE);
+        if !file.is_file() {
+            continue;
+        }
+        let contents = fs::read_to_string(&file)?;
+        let origin = toml::from_str(&contents)
+            .map_err(|e| PiError::Parse(file.to_string_lossy().to_string(), e))?;
+        templates.push(Installed {
+            name: entry.file_name().to_string_lossy().to_string(),
+            path,
+            origin,
+        });
+    }
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(templates)
+}
+
+/// Remove the installed template `name`.
+pub fn remove(name: &str, home: &Path) -> Result<Installed, PiError> {
+    check_name(name)?;
+    let template = installed(home)?
+        .into_iter()
+        .find(|t| t.name == name)
+        .ok_or_else(|| PiError::NotInstalled(name.to_string()))?;
+    fs::remove_dir_all(&template.path)?;
+    Ok(template)
+}
+
+/// Install the latest version of `name`, or of every installed template if
+/// `name` isn't given, from where it was installed from. Returns each along
+/// with what was installed before.
+pub fn update(
+    name: Option<&str>,
+    home: &Path,
+    git: &GitConfig,
+) -> Result<Vec<(Installed, Option<String>)>, PiError> {
+    if let Some(name) = name {
+        check_name(name)?;
+    }
+    let templates: Vec<Installed> = installed(home)?
+        .into_iter()
+        .filter(|t| name.map(|n| n == t.name).unwrap_or(true))
+        .collect();
+    if let (Some(name), true) = (name, templates.is_empty()) {
+        return Err(PiError::NotInstalled(name.to_string()));
+    }
+    let mut updated = Vec::new();
+    for template in templates {
+        let (stage, origin) = stage(&template.origin, home, git)?;
+        fs::remove_dir_all(&template.path)?;
+        fs::rename(stage.path(), &template.path)?;
+        let _ = stage.into_path();
+        let before = template.origin.installed;
+        updated.push((Installed { origin, ..template }, before));
+    }
+    Ok(updated)
+}
+
+// Templates are directories right in the user template directory, so a name
+// can't lead anywhere else.
+fn check_name(name: &str) -> Result<(), PiError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(PiError::InvalidTemplateName(name.to_string()));
+    }
+    Ok(())
+}
+
+// Copy the template from `origin` into a new directory next to where it's
+// installed, so it can be moved into place whole. Returns it along with
+// `origin` as installed.
+fn stage(origin: &Origin, home: &Path, git: &GitConfig) -> Result<(TempDir, Origin), PiError> {
+    let mut origin = origin.clone();
+    let location = origin.location.clone();
+    // kept until the template is copied
+    let fetched = match origin.kind {
+        Kind::Git => {
+            let revision = origin.revision.as_deref();
+            let subdir = origin.subdir.as_deref();
+            let checkout = remote::clone(&location, revision, subdir, git)?;
+            origin.installed = Repository::open(checkout.path()).ok().and_then(|r| {
+                let commit = r.head().ok()?.peel_to_commit().ok()?;
+                Some(commit.id().to_string())
+            });
+            let dir = checkout.path().join(subdir.unwrap_or(""));
+            Some((checkout, dir))
+        }
+        Kind::Archive => {
+            let unpacked = TempDir::new("pi-template")?;
+            let contents = archive::fetch(&location, unpacked.path())?;
+            origin.installed = Some(sha256(&contents));
+            let dir = archive::root(unpacked.path());
+            Some((unpacked, dir))
+        }
+        _ => None,
+    };
+    let dir = match fetched {
+        Some((_, ref dir)) => dir.clone(),
+        None => PathBuf::from(&location),
+    };
+    if !dir.join("template.toml").exists() {
+        // the repository may hold several templates instead
+        if let Some(entries) = catalog::read(&dir)? {
+            let names = entries.into_iter().map(|e| e.name).collect();
+            return Err(PiError::ChooseTemplate(origin.source(), names));
+        }
+        let mut path = origin.source();
+        path.push_str("/template.toml");
+        return Err(PiError::MissingTemplate(path));
+    }
+    let root = user_dir(home);
+    fs::create_dir_all(&root)?;
+    let stage = TempDir::new_in(&root, ".install")?;
+    copy_dir(&dir, stage.path())?;
+    // a copy of the repository isn't needed, nor is a record of another install
+    let git_dir = stage.path().join(".git");
+    if git_dir.is_dir() {
+        fs::remove_dir_all(git_dir)?;
+    }
+    let meta = toml::to_string(&origin).unwrap_or_default();
+    fs::write(stage.path().join(FILE), meta)?;
+    Ok((stage, origin))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A home directory, and a template in it to install.
+    fn setup() -> (TempDir, Origin) {
+        let home = TempDir::new("pi-templates").unwrap();
+        let source = home.path().join("source");
+        fs::create_dir(&source).unwrap();
+        fs::write(source.join("template.toml"), "[files]\n").unwrap();
+        fs::write(source.join("README.md"), "old").unwrap();
+        let origin = Origin::parse(&source.to_string_lossy()).unwrap();
+        (home, origin)
+    }
+
+    #[test]
+    fn install_copies_the_template() {
+        let (home, origin) = setup();
+        let installed = install("t", &origin, home.path(), &GitConfig::default()).unwrap();
+        assert_eq!(installed.path, user_dir(home.path()).join("t"));
+        assert!(installed.path.join("README.md").is_file());
+        assert_eq!(self::origin(&installed.path), Some(origin.clone()));
+        let again = install("t", &origin, home.path(), &GitConfig::default());
+        assert!(matches!(again, Err(PiError::AlreadyInstalled(_, _))));
+    }
+
+    #[test]
+    fn names_stay_in_the_template_directory() {
+        let (home, origin) = setup();
+        for name in &["", ".", "..", "../t", "a/b", "a\\b"] {
+            let result = install(name, &origin, home.path(), &GitConfig::default());
+            assert!(matches!(result, Err(PiError::InvalidTemplateName(_))));
+            assert!(matches!(
+                remove(name, home.path()),
+                Err(PiError::InvalidTemplateName(_))
+            ));
+        }
+        assert!(!home.path().join("t").exists());
+    }
+
+    #[test]
+    fn update_installs_the_latest_version() {
+        let (home, origin) = setup();
+        install("t", &origin, home.path(), &GitConfig::default()).unwrap();
+        fs::write(Path::new(&origin.location).join("README.md"), "new").unwrap();
+        let updated = update(Some("t"), home.path(), &GitConfig::default()).unwrap();
+        assert_eq!(updated.len(), 1);
+        let readme = user_dir(home.path()).join("t").join("README.md");
+        assert_eq!(fs::read_to_string(readme).unwrap(), "new");
+        let missing = update(Some("u"), home.path(), &GitConfig::default());
+        assert!(matches!(missing, Err(PiError::NotInstalled(_))));
+    }
+
+    #[test]
+    fn remove_deletes_installed_templates() {
+        let (home, origin) = setup();
+        install("t", &origin, home.path(), &GitConfig::default()).unwrap();
+        let removed = remove("t", home.path()).unwrap();
+        assert!(!removed.path.exists());
+        assert!(installed(home.path()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn remove_leaves_templates_not_installed() {
+        let (home, _) = setup();
+        let mine = user_dir(home.path()).join("mine");
+        fs::create_dir_all(&mine).unwrap();
+        fs::write(mine.join("template.toml"), "").unwrap();
+        let result = remove("mine", home.path());
+        assert!(matches!(result, Err(PiError::NotInstalled(_))));
+        assert!(mine.join("template.toml").exists());
+    }
+}
@@ -18,6 +18,20 @@ pub struct Config {
     pub author: Option<Author>,
     pub license: Option<String>,
     pub user: Option<UserConfig>,
+    pub git: Option<GitConfig>,
+    /// Registry indexes to search for templates, as paths or URLs, besides
+    /// the one built in.
+    pub registries: Option<Vec<String>>,
+}
+
+/// Struct for the `[git]` table of the global configuration, used to fetch
+/// private template repositories.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct GitConfig {
+    /// SSH private key to try if the ssh-agent doesn't have one that works.
+    pub ssh_key: Option<String>,
+    /// User name to authenticate with, if the URL doesn't have one.
+    pub username: Option<String>,
 }
 
 /// Struct for directories, files, templates, and scripts to be created.
@@ -34,6 +48,9 @@ pub struct Directory {
 pub struct ProjectConfig {
     pub version_control: Option<String>,
     pub version: Option<String>,
+    pub line_endings: Option<String>,
+    pub trailing_newline: Option<bool>,
+    pub bom: Option<bool>,
 }
 
 /// Struct for a project
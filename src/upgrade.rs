@@ -0,0 +1,255 @@
+//! Module for bringing a generated project up to date with its template.
+extern crate diffy;
+
+use answers;
+use answers::{Answers, Kind};
+use archive::Entry;
+use colored::*;
+use error::PiError;
+use filesystem::Memory;
+use generator::{Generator, Source};
+use manifest;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// What `upgrade` did to the project.
+#[derive(Debug, Clone, Default)]
+pub struct Upgrade {
+    /// Revision of the template the project is now at.
+    pub revision: Option<String>,
+    /// Files and directories new in the template.
+    pub created: Vec<String>,
+    /// Files replaced by the template's new version, as they hadn't been
+    /// changed locally.
+    pub updated: Vec<String>,
+    /// Files where local changes and changes to the template were combined.
+    pub merged: Vec<String>,
+    /// Files where local changes and changes to the template overlap. These
+    /// now contain conflict markers.
+    pub conflicts: Vec<String>,
+    /// Files the template no longer has, which hadn't been changed locally.
+    pub removed: Vec<String>,
+}
+
+impl Upgrade {
+    /// Print what happened to each file.
+    pub fn summary(&self) {
+        for c in &self.created {
+            println!("{}: {}", "Created".green(), c);
+        }
+        for u in &self.updated {
+            println!("{}: {}", "Updated".green(), u);
+        }
+        for m in &self.merged {
+            println!("{}: {}", "Merged".cyan(), m);
+        }
+        for r in &self.removed {
+            println!("{}: {}", "Removed".yellow(), r);
+        }
+        for c in &self.conflicts {
+            println!("{}: {}", "Conflict".red(), c);
+        }
+    }
+}
+
+/// Upgrade the project in `dir` to the latest version of its template, or to
+/// `revision` if given, using the answers recorded when it was generated.
+///
+/// Both versions of the template are rendered, and the changes between them
+/// are merged into the project. `configure` is applied to both generators,
+/// e.g. to pass in the global configuration.
+pub fn upgrade<F>(dir: &str, revision: Option<&str>, configure: F) -> Result<Upgrade, PiError>
+where
+    F: Fn(Generator) -> Generator,
+{
+    let project = Path::new(dir);
+    let answers_path = project.join(answers::FILE).to_string_lossy().to_string();
+    let answers = Answers::read(&answers_path)?;
+    let name = answers
+        .variables
+        .get("project")
+        .cloned()
+        .ok_or_else(|| PiError::NoProjectName(answers_path.clone()))?;
+
+    // the template as the project was generated from it
+    let old_revision = answers.template.revision.as_deref();
+    let old = match (answers.template.kind, old_revision) {
+        (Kind::Builtin, Some(v)) if v != env!("CARGO_PKG_VERSION") => {
+            return Err(PiError::NotUpgradable(format!(
+                "the project was generated by pi {}, and the builtin templates of other versions aren't available",
+                v
+            )));
+        }
+        (Kind::Builtin, _) => None,
+        (Kind::Archive, _) => {
+            return Err(PiError::NotUpgradable(format!(
+                "{} is an archive, so the version the project was generated from isn't available",
+                answers.template.location
+            )));
+        }
+        // a template directory that's a repository can be cloned at the old revision
+        (Kind::Dir, Some(rev)) | (Kind::Git, Some(rev)) => {
+            let source = Source::Git(answers.template.location.clone());
+            Some(answers.generator_for(source, Some(rev), &name))
+        }
+        (Kind::Hg, Some(rev)) | (Kind::Darcs, Some(rev)) | (Kind::Pijul, Some(rev)) => {
+            Some(answers.generator_for(answers.source(), Some(rev), &name))
+        }
+        (_, None) => {
+            return Err(PiError::NotUpgradable(format!(
+                "no revision of {} was recorded, so its old version can't be rendered",
+                answers.template.location
+            )));
+        }
+    };
+    let new = answers.generator_for(answers.source(), revision, &name);
+
+    let (new, new_revision) = render(configure(new))?;
+    let old = match old {
+        Some(g) => render(configure(g))?.0,
+        // builtins haven't changed since
+        None => new.clone(),
+    };
+
+    let mut upgrade = Upgrade {
+        revision: new_revision,
+        ..Upgrade::default()
+    };
+    // what changed, relative to the project, for the manifest
+    let mut written = Vec::new();
+    let mut created = Vec::new();
+    let mut removed = Vec::new();
+    for (path, entry) in &new {
+        // the manifest is brought up to date below
+        if path == manifest::FILE {
+            continue;
+        }
+        let target = project.join(path);
+        let shown = target.to_string_lossy().to_string();
+        let (theirs, mode) = match *entry {
+            Entry::Dir => {
+                if !target.exists() {
+                    fs::create_dir_all(&target)?;
+                    upgrade.created.push(shown);
+                    created.push(path.clone());
+                }
+                continue;
+            }
+            Entry::File { ref contents, mode } => (contents, mode),
+        };
+        let base = match old.get(path) {
+            Some(Entry::File { contents, .. }) => Some(contents),
+            _ => None,
+        };
+        let ours = match fs::read(&target) {
+            Ok(o) => o,
+            Err(_) => {
+                // a file that was deleted locally stays deleted
+                if base.is_none() {
+                    write(&target, theirs, mode)?;
+                    upgrade.created.push(shown);
+                    written.push(path.clone());
+                }
+                continue;
+            }
+        };
+        if ours == *theirs || base == Some(theirs) {
+            continue;
+        }
+        if path == answers::FILE || base == Some(&ours) {
+            write(&target, theirs, mode)?;
+            upgrade.updated.push(shown);
+            written.push(path.clone());
+            continue;
+        }
+        let base: &[u8] = base.map(|b| b.as_slice()).unwrap_or(&[]);
+        match diffy::merge_bytes(base, &ours, theirs) {
+            Ok(merged) => {
+                fs::write(&target, merged)?;
+                upgrade.merged.push(shown);
+            }
+            Err(conflicted) => {
+                fs::write(&target, conflicted)?;
+                upgrade.conflicts.push(shown);
+            }
+        }
+    }
+
+    // remove what the template dropped, unless it was changed locally
+    for (path, entry) in old.iter().rev() {
+        if new.contains_key(path) {
+            continue;
+        }
+        let target = project.join(path);
+        let shown = target.to_string_lossy().to_string();
+        match *entry {
+            Entry::Dir => {
+                // only if it's empty now
+                if fs::remove_dir(&target).is_ok() {
+                    upgrade.removed.push(shown);
+                    removed.push(path.clone());
+                }
+            }
+            Entry::File { ref contents, .. } => match fs::read(&target) {
+                Ok(ref ours) if ours == contents => {
+                    fs::remove_file(&target)?;
+                    upgrade.removed.push(shown);
+                    removed.push(path.clone());
+                }
+                Ok(_) => eprintln!(
+                    "{}: the template no longer has {}, but it has local changes, so it was kept",
+                    "Warning".yellow(),
+                    shown
+                ),
+                Err(_) => (),
+            },
+        }
+    }
+    // merged files keep their old hashes, as they have local changes
+    manifest::record(project, &written, &created, &removed)?;
+    Ok(upgrade)
+}
+
+// Generate the project in memory, returning what was rendered keyed by path
+// within the project, along with the template's revision.
+fn render(generator: Generator) -> Result<(BTreeMap<String, Entry>, Option<String>), PiError> {
+    let memory = Memory::new();
+    let report = generator
+        .filesystem(Box::new(memory.clone()))
+        .skip_version_control()
+        .generate()?;
+    let entries = memory
+        .entries()
+        .into_iter()
+        .filter_map(|(path, entry)| {
+            // everything is under a directory named after the project
+            let rest = path.split_once('/')?.1.to_string();
+            Some((rest, entry))
+        })
+        .collect();
+    Ok((entries, report.revision))
+}
+
+fn write(path: &Path, contents: &[u8], mode: u32) -> Result<(), PiError> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, contents)?;
+    set_mode(path, mode)?;
+    Ok(())
+}
+
+#[cfg(not(target_os = "windows"))]
+fn set_mode(path: &Path, mode: u32) -> Result<(), PiError> {
+    use std::os::unix::fs::PermissionsExt;
+    if mode & 0o111 != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(mode))?;
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "windows")]
+fn set_mode(_: &Path, _: u32) -> Result<(), PiError> {
+    Ok(())
+}
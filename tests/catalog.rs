@@ -0,0 +1,88 @@
+//! Listing the templates in a repository's catalog, with `pi git REPO` and no
+//! project name.
+extern crate tempdir;
+
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+use tempdir::TempDir;
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("failed to run git");
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+fn has_git() -> bool {
+    Command::new("git")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+// A repository in `home` holding `files`, and a configuration file so pi
+// doesn't ask for an author.
+fn repository(home: &Path, files: &[(&str, &str)]) -> String {
+    fs::write(
+        home.join(".pi.toml"),
+        "[author]\nname = \"Test\"\nemail = \"test@example.com\"\n",
+    )
+    .unwrap();
+    let repo = home.join("repo");
+    fs::create_dir(&repo).unwrap();
+    for (name, contents) in files {
+        fs::write(repo.join(name), contents).unwrap();
+    }
+    git(&repo, &["init", "-q"]);
+    git(&repo, &["add", "-A"]);
+    git(&repo, &["commit", "-q", "-m", "templates"]);
+    repo.to_string_lossy().to_string()
+}
+
+fn pi_git(home: &Path, repo: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_pi"))
+        .args(["git", repo])
+        .current_dir(home)
+        .env("HOME", home)
+        .env("XDG_CACHE_HOME", home.join("cache"))
+        .env_remove("XDG_CONFIG_HOME")
+        .stdin(Stdio::null())
+        .output()
+        .expect("failed to run pi")
+}
+
+#[test]
+fn lists_the_catalog() {
+    if !has_git() {
+        return;
+    }
+    let home = TempDir::new("pi-catalog").unwrap();
+    let catalog = "[[template]]\nname = \"web\"\npath = \"web\"\n";
+    let repo = repository(home.path(), &[("templates.toml", catalog)]);
+    let output = pi_git(home.path(), &repo);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("web"));
+}
+
+#[test]
+fn asks_for_a_name_without_a_catalog() {
+    if !has_git() {
+        return;
+    }
+    let home = TempDir::new("pi-catalog").unwrap();
+    let repo = repository(home.path(), &[("template.toml", "[files]\n")]);
+    let output = pi_git(home.path(), &repo);
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("a project name is required"), "{}", stderr);
+}
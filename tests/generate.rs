@@ -0,0 +1,142 @@
+//! Generating projects from template directories on disk, including into
+//! directories that already exist.
+extern crate project_init;
+extern crate tempdir;
+
+use project_init::error::PiError;
+use project_init::generator::{Generator, Report, Source, VcsStatus};
+use project_init::manifest;
+use project_init::render::Force;
+use project_init::types::Author;
+use std::fs;
+use std::path::Path;
+use tempdir::TempDir;
+
+// A template in `dir` with a README and an executable script.
+fn template(dir: &Path) -> String {
+    let path = dir.join("tmpl");
+    fs::create_dir(&path).unwrap();
+    fs::write(
+        path.join("template.toml"),
+        "license = \"MIT\"\n\n[files]\ntemplates = [\"README.md\"]\nscripts = [\"run.sh\"]\n",
+    )
+    .unwrap();
+    fs::write(path.join("README.md"), "# {{ project }}\n").unwrap();
+    fs::write(path.join("run.sh"), "#!/bin/sh\necho {{ project }}\n").unwrap();
+    path.to_string_lossy().to_string()
+}
+
+fn generator(source: Source, project: &Path) -> Generator {
+    Generator::new(source, &project.to_string_lossy())
+        .author(Author {
+            name: "Test".to_string(),
+            email: "test@example.com".to_string(),
+            github_username: None,
+        })
+        .date(2024, "2024-01-01")
+        .home(project.parent().unwrap().to_path_buf())
+}
+
+fn generate(template: &str, project: &Path, force: Option<Force>) -> Result<Report, PiError> {
+    generate_from(Source::Dir(template.to_string()), project, force, false)
+}
+
+fn generate_from(
+    source: Source,
+    project: &Path,
+    force: Option<Force>,
+    merge: bool,
+) -> Result<Report, PiError> {
+    let mut generator = generator(source, project)
+        .merge(merge)
+        .skip_version_control();
+    if let Some(f) = force {
+        generator = generator.force(f);
+    }
+    generator.generate()
+}
+
+#[cfg(not(target_os = "windows"))]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path).unwrap().permissions().mode() & 0o111 != 0
+}
+
+#[cfg(target_os = "windows")]
+fn is_executable(_: &Path) -> bool {
+    true
+}
+
+#[test]
+fn regenerate_with_force() {
+    let dir = TempDir::new("pi-generate").unwrap();
+    let tmpl = template(dir.path());
+    let project = dir.path().join("proj");
+    generate(&tmpl, &project, None).unwrap();
+    // nothing has changed, and again once something has
+    generate(&tmpl, &project, Some(Force::Overwrite)).unwrap();
+    fs::write(project.join("run.sh"), "changed\n").unwrap();
+    generate(&tmpl, &project, Some(Force::Overwrite)).unwrap();
+    let script = project.join("run.sh");
+    assert_eq!(
+        fs::read_to_string(&script).unwrap(),
+        "#!/bin/sh\necho proj\n"
+    );
+    assert!(is_executable(&script));
+}
+
+#[test]
+fn report_has_the_full_path() {
+    let dir = TempDir::new("pi-generate").unwrap();
+    let tmpl = template(dir.path());
+    fs::create_dir(dir.path().join("sub")).unwrap();
+    let project = dir.path().join("sub").join("..").join("proj");
+    let report = generate(&tmpl, &project, None).unwrap();
+    let full = dir.path().canonicalize().unwrap().join("proj");
+    assert_eq!(report.output, full.to_string_lossy());
+}
+
+#[test]
+fn regenerate_builtin_with_merge_and_force() {
+    let dir = TempDir::new("pi-generate").unwrap();
+    let project = dir.path().join("m");
+    let miso = || Source::Builtin("miso".to_string());
+    generate_from(miso(), &project, None, false).unwrap();
+    generate_from(miso(), &project, None, true).unwrap();
+    generate_from(miso(), &project, Some(Force::Overwrite), false).unwrap();
+    assert!(is_executable(&project.join("shake.hs")));
+}
+
+#[test]
+fn existing_directories_get_no_repository() {
+    let dir = TempDir::new("pi-generate").unwrap();
+    let tmpl = template(dir.path());
+    let mut toml = fs::read_to_string(Path::new(&tmpl).join("template.toml")).unwrap();
+    toml.push_str("\n[config]\nversion_control = \"git\"\n");
+    fs::write(Path::new(&tmpl).join("template.toml"), toml).unwrap();
+    let project = dir.path().join("proj");
+    fs::create_dir(&project).unwrap();
+    fs::write(project.join("untracked.env"), "SECRET=1\n").unwrap();
+    let report = generator(Source::Dir(tmpl.clone()), &project)
+        .force(Force::Overwrite)
+        .generate()
+        .unwrap();
+    assert_eq!(
+        report.version_control,
+        VcsStatus::Skipped("git".to_string())
+    );
+    assert!(!project.join(".git").exists());
+}
+
+#[test]
+fn undo_after_regenerating() {
+    let dir = TempDir::new("pi-generate").unwrap();
+    let tmpl = template(dir.path());
+    let project = dir.path().join("proj");
+    generate(&tmpl, &project, None).unwrap();
+    generate(&tmpl, &project, Some(Force::Overwrite)).unwrap();
+    generate_from(Source::Dir(tmpl.clone()), &project, None, true).unwrap();
+    let undo = manifest::undo(&project.to_string_lossy(), false).unwrap();
+    assert!(undo.kept.is_empty());
+    assert!(!project.exists());
+}
@@ -0,0 +1,307 @@
+//! Fetching templates from a git server that wants a password, served over
+//! HTTP by `git http-backend` behind basic authentication.
+extern crate tempdir;
+
+use std::fs;
+use std::io::prelude::*;
+use std::io::BufReader;
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use tempdir::TempDir;
+
+const USER: &str = "alice";
+const PASSWORD: &str = "s3cret";
+
+fn base64(input: &[u8]) -> String {
+    const CHARS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in input.chunks(3) {
+        let b = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(CHARS[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("failed to run git");
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+// Whether git, along with `git http-backend`, is there to test against.
+fn has_git() -> bool {
+    Command::new("git")
+        .args(["http-backend", "-h"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+// A bare repository `t.git` in `root`, holding a template.
+fn make_repository(root: &Path) {
+    let work = root.join("work");
+    fs::create_dir(&work).unwrap();
+    fs::write(
+        work.join("template.toml"),
+        "license = \"MIT\"\n\n[files]\ntemplates = [\"README.md\"]\n",
+    )
+    .unwrap();
+    fs::write(work.join("README.md"), "# {{ project }}\n").unwrap();
+    git(&work, &["init", "-q"]);
+    git(&work, &["add", "-A"]);
+    git(&work, &["commit", "-q", "-m", "template"]);
+    git(root, &["clone", "-q", "--bare", "work", "t.git"]);
+}
+
+struct Request {
+    method: String,
+    path: String,
+    // names in lower case
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Request {
+    fn header(&self, name: &str) -> Option<String> {
+        header(&self.headers, name)
+    }
+}
+
+fn header(headers: &[(String, String)], name: &str) -> Option<String> {
+    headers.iter().find(|h| h.0 == name).map(|h| h.1.clone())
+}
+
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+    let mut headers = Vec::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        let (k, v) = line.split_once(':')?;
+        headers.push((k.to_lowercase(), v.trim().to_string()));
+    }
+    let mut body = Vec::new();
+    if header(&headers, "transfer-encoding").as_deref() == Some("chunked") {
+        loop {
+            let mut size = String::new();
+            reader.read_line(&mut size).ok()?;
+            let size = usize::from_str_radix(size.trim(), 16).ok()?;
+            let mut chunk = vec![0; size + 2];
+            reader.read_exact(&mut chunk).ok()?;
+            if size == 0 {
+                break;
+            }
+            body.extend_from_slice(&chunk[..size]);
+        }
+    } else if let Some(n) = header(&headers, "content-length") {
+        body = vec![0; n.parse().ok()?];
+        reader.read_exact(&mut body).ok()?;
+    }
+    Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+// Answer a request, passing it on to `git http-backend` if it has the right
+// password.
+fn handle(mut stream: TcpStream, root: &Path) {
+    let request = match read_request(&stream) {
+        Some(r) => r,
+        None => return,
+    };
+    let mut want = "Basic ".to_string();
+    want.push_str(&base64(format!("{}:{}", USER, PASSWORD).as_bytes()));
+    if request.header("authorization") != Some(want) {
+        let _ = stream.write_all(
+            b"HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"git\"\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        return;
+    }
+    let (path_info, query) = match request.path.split_once('?') {
+        Some((p, q)) => (p.to_string(), q.to_string()),
+        None => (request.path.clone(), String::new()),
+    };
+    let mut backend = Command::new("git")
+        .arg("http-backend")
+        .env("GIT_PROJECT_ROOT", root)
+        .env("GIT_HTTP_EXPORT_ALL", "1")
+        .env("PATH_INFO", path_info)
+        .env("QUERY_STRING", query)
+        .env("REQUEST_METHOD", &request.method)
+        .env(
+            "CONTENT_TYPE",
+            request.header("content-type").unwrap_or_default(),
+        )
+        .env("CONTENT_LENGTH", request.body.len().to_string())
+        .env("REMOTE_USER", USER)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .expect("failed to run git http-backend");
+    backend
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(&request.body)
+        .unwrap();
+    let out = backend.wait_with_output().unwrap().stdout;
+    let split = out
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .unwrap_or(out.len());
+    let head = String::from_utf8_lossy(&out[..split]).to_string();
+    let rest = out.get(split + 4..).unwrap_or(&[]);
+    let mut status = "200 OK".to_string();
+    let mut response = String::new();
+    for line in head.lines() {
+        match line.split_once(": ") {
+            Some(("Status", s)) => status = s.to_string(),
+            Some(_) => {
+                response.push_str(line);
+                response.push_str("\r\n");
+            }
+            None => (),
+        }
+    }
+    let response = format!(
+        "HTTP/1.1 {}\r\n{}Content-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        response,
+        rest.len()
+    );
+    let _ = stream.write_all(response.as_bytes());
+    let _ = stream.write_all(rest);
+}
+
+// Serve the repositories in `root`, returning the URL they're at.
+fn serve(root: PathBuf) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            handle(stream, &root);
+        }
+    });
+    format!("http://127.0.0.1:{}/", port)
+}
+
+// A home directory with a configuration file, and a place for the project.
+fn home(config: &str) -> TempDir {
+    let home = TempDir::new("pi-home").unwrap();
+    let mut toml = "[author]\nname = \"Test\"\nemail = \"test@example.com\"\n".to_string();
+    toml.push_str(config);
+    fs::write(home.path().join(".pi.toml"), toml).unwrap();
+    home
+}
+
+fn pi(home: &Path, url: &str, token: Option<&str>) -> Output {
+    let mut command = Command::new(env!("CARGO_BIN_EXE_pi"));
+    command
+        .args(["git", url, "proj"])
+        .current_dir(home)
+        .env("HOME", home)
+        .env_remove("XDG_CACHE_HOME")
+        .env_remove("XDG_CONFIG_HOME")
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .env_remove("PI_GIT_TOKEN");
+    if let Some(t) = token {
+        command.env("PI_GIT_TOKEN", t);
+    }
+    command.output().expect("failed to run pi")
+}
+
+fn assert_generated(home: &Path, output: &Output) {
+    assert!(
+        output.status.success(),
+        "pi failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let readme = fs::read_to_string(home.join("proj").join("README.md")).unwrap();
+    assert_eq!(readme, "# proj\n");
+}
+
+#[test]
+fn token_from_the_environment() {
+    if !has_git() {
+        return;
+    }
+    let root = TempDir::new("pi-server").unwrap();
+    make_repository(root.path());
+    let url = serve(root.path().to_path_buf()) + "t.git";
+
+    let home = home("[git]\nusername = \"alice\"\n");
+    let output = pi(home.path(), &url, Some("wrong"));
+    assert!(!output.status.success());
+    assert!(!home.path().join("proj").exists());
+
+    let output = pi(home.path(), &url, Some(PASSWORD));
+    assert_generated(home.path(), &output);
+}
+
+#[test]
+fn credential_helper() {
+    if !has_git() {
+        return;
+    }
+    let root = TempDir::new("pi-server").unwrap();
+    make_repository(root.path());
+    // git2 only asks credential helpers about hosts given by name
+    let base = serve(root.path().to_path_buf()).replace("127.0.0.1", "localhost");
+    let url = base.clone() + "t.git";
+
+    // no token, and nothing to ask
+    let home = home("");
+    let output = pi(home.path(), &url, None);
+    assert!(!output.status.success());
+
+    // the user name comes from the helper as well
+    let mut stored =
+        base.trim_end_matches('/')
+            .replacen("://", &format!("://{}:{}@", USER, PASSWORD), 1);
+    stored.push('\n');
+    fs::write(home.path().join(".git-credentials"), stored).unwrap();
+    fs::write(
+        home.path().join(".gitconfig"),
+        "[credential]\n\thelper = store\n",
+    )
+    .unwrap();
+    let output = pi(home.path(), &url, None);
+    assert_generated(home.path(), &output);
+}
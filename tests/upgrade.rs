@@ -0,0 +1,172 @@
+//! Upgrading generated projects once their template has changed, from a
+//! template directory that's a git repository.
+extern crate project_init;
+extern crate tempdir;
+
+use project_init::generator::{Generator, Source};
+use project_init::types::Author;
+use project_init::upgrade::{self, Upgrade};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use tempdir::TempDir;
+
+const README: &str = "# {{ project }}\n\none\ntwo\nthree\n";
+const TOML: &str = "license = \"MIT\"\n\n[files]\ntemplates = [\"README.md\", \"notes.txt\"]\n";
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .env("GIT_AUTHOR_NAME", "Test")
+        .env("GIT_AUTHOR_EMAIL", "test@example.com")
+        .env("GIT_COMMITTER_NAME", "Test")
+        .env("GIT_COMMITTER_EMAIL", "test@example.com")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .expect("failed to run git");
+    assert!(status.success(), "git {:?} failed", args);
+}
+
+fn has_git() -> bool {
+    Command::new("git")
+        .arg("--version")
+        .stdout(Stdio::null())
+        .status()
+        .is_ok()
+}
+
+fn configure(g: Generator, home: &Path) -> Generator {
+    g.author(Author {
+        name: "Test".to_string(),
+        email: "test@example.com".to_string(),
+        github_username: None,
+    })
+    .date(2024, "2024-01-01")
+    .home(home.to_path_buf())
+}
+
+// A template repository in `dir`, and a project generated from its first
+// commit.
+fn setup(dir: &Path) -> (PathBuf, PathBuf) {
+    let tmpl = dir.join("tmpl");
+    fs::create_dir(&tmpl).unwrap();
+    fs::write(tmpl.join("template.toml"), TOML).unwrap();
+    fs::write(tmpl.join("README.md"), README).unwrap();
+    fs::write(tmpl.join("notes.txt"), "notes\n").unwrap();
+    git(&tmpl, &["init", "-q"]);
+    commit(&tmpl);
+    let project = dir.join("proj");
+    let source = Source::Dir(tmpl.to_string_lossy().to_string());
+    configure(Generator::new(source, &project.to_string_lossy()), dir)
+        .skip_version_control()
+        .generate()
+        .unwrap();
+    (tmpl, project)
+}
+
+fn commit(tmpl: &Path) {
+    git(tmpl, &["add", "-A"]);
+    git(tmpl, &["commit", "-q", "-m", "template"]);
+}
+
+fn run(dir: &Path, project: &Path) -> Upgrade {
+    upgrade::upgrade(&project.to_string_lossy(), None, |g| configure(g, dir)).unwrap()
+}
+
+fn shown(project: &Path, file: &str) -> String {
+    project.join(file).to_string_lossy().to_string()
+}
+
+#[test]
+fn clean_upgrade() {
+    if !has_git() {
+        return;
+    }
+    let dir = TempDir::new("pi-upgrade").unwrap();
+    let (tmpl, project) = setup(dir.path());
+    fs::write(tmpl.join("README.md"), README.replace("two", "2")).unwrap();
+    commit(&tmpl);
+    let upgrade = run(dir.path(), &project);
+    assert!(upgrade.updated.contains(&shown(&project, "README.md")));
+    assert!(upgrade.conflicts.is_empty());
+    assert_eq!(
+        fs::read_to_string(project.join("README.md")).unwrap(),
+        "# proj\n\none\n2\nthree\n"
+    );
+}
+
+#[test]
+fn local_changes_are_merged() {
+    if !has_git() {
+        return;
+    }
+    let dir = TempDir::new("pi-upgrade").unwrap();
+    let (tmpl, project) = setup(dir.path());
+    fs::write(project.join("README.md"), "# proj\n\none\ntwo\n3\n").unwrap();
+    fs::write(tmpl.join("README.md"), README.replace("one", "1")).unwrap();
+    commit(&tmpl);
+    let upgrade = run(dir.path(), &project);
+    assert_eq!(upgrade.merged, vec![shown(&project, "README.md")]);
+    assert_eq!(
+        fs::read_to_string(project.join("README.md")).unwrap(),
+        "# proj\n\n1\ntwo\n3\n"
+    );
+}
+
+#[test]
+fn overlapping_changes_conflict() {
+    if !has_git() {
+        return;
+    }
+    let dir = TempDir::new("pi-upgrade").unwrap();
+    let (tmpl, project) = setup(dir.path());
+    fs::write(project.join("README.md"), "# proj\n\none\nmine\nthree\n").unwrap();
+    fs::write(tmpl.join("README.md"), README.replace("two", "theirs")).unwrap();
+    commit(&tmpl);
+    let upgrade = run(dir.path(), &project);
+    assert_eq!(upgrade.conflicts, vec![shown(&project, "README.md")]);
+    let readme = fs::read_to_string(project.join("README.md")).unwrap();
+    assert!(readme.contains("<<<<<<<"));
+    assert!(readme.contains("mine"));
+    assert!(readme.contains("theirs"));
+}
+
+#[test]
+fn deleted_files_stay_deleted() {
+    if !has_git() {
+        return;
+    }
+    let dir = TempDir::new("pi-upgrade").unwrap();
+    let (tmpl, project) = setup(dir.path());
+    fs::remove_file(project.join("notes.txt")).unwrap();
+    fs::write(tmpl.join("notes.txt"), "more notes\n").unwrap();
+    commit(&tmpl);
+    let upgrade = run(dir.path(), &project);
+    assert!(upgrade.created.is_empty());
+    assert!(!upgrade.updated.contains(&shown(&project, "notes.txt")));
+    assert!(!project.join("notes.txt").exists());
+}
+
+#[test]
+fn new_files_are_created() {
+    if !has_git() {
+        return;
+    }
+    let dir = TempDir::new("pi-upgrade").unwrap();
+    let (tmpl, project) = setup(dir.path());
+    fs::write(
+        tmpl.join("template.toml"),
+        TOML.replace("\"notes.txt\"", "\"notes.txt\", \"CHANGELOG.md\""),
+    )
+    .unwrap();
+    fs::write(tmpl.join("CHANGELOG.md"), "# Changes\n").unwrap();
+    commit(&tmpl);
+    let upgrade = run(dir.path(), &project);
+    assert_eq!(upgrade.created, vec![shown(&project, "CHANGELOG.md")]);
+    assert_eq!(
+        fs::read_to_string(project.join("CHANGELOG.md")).unwrap(),
+        "# Changes\n"
+    );
+}